@@ -0,0 +1,19 @@
+fn main() {
+    // with the ffi feature, write the C header for src/ffi.rs to
+    // $OUT_DIR/markov_chain.h, configured by cbindgen.toml
+    #[cfg(feature = "ffi")]
+    {
+        let crate_dir = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+        let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+            .expect("failed to read cbindgen.toml");
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(crate_dir.join("src/ffi.rs"))
+            .generate()
+            .expect("failed to generate the C header")
+            .write_to_file(out_dir.join("markov_chain.h"));
+    }
+}
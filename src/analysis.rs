@@ -0,0 +1,715 @@
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+
+use crate::error::MarkovError;
+use crate::probability::successor_distribution;
+use crate::types::{Map, MarkovChain, MarkovNode, MarkovNodeID};
+
+#[doc = "The entropy function returns the Shannon entropy (in bits) of the"]
+#[doc = "distribution given by the provided links' portions."]
+fn entropy(links: &Map<MarkovNodeID, u64>) -> f64 {
+    let total: u64 = links.iter().map(|(_, portions)| *portions).sum();
+    if total == 0 {
+        return 0.0;
+    }
+    links
+        .iter()
+        .filter(|(_, portions)| **portions > 0)
+        .map(|(_, portions)| {
+            let probability = *portions as f64 / total as f64;
+            -probability * probability.log2()
+        })
+        .sum()
+}
+
+impl<T: Eq + Hash + Clone> MarkovChain<T> {
+    #[doc = "The reachable_ids function does a breadth-first search over the"]
+    #[doc = "links starting at the node with the provided id, and returns the"]
+    #[doc = "ids of every node it visited (including the starting one). Links"]
+    #[doc = "back to the root end a sentence, so they are not followed."]
+    pub(crate) fn reachable_ids(&self, start: MarkovNodeID) -> Vec<MarkovNodeID> {
+        let mut visited = vec![start];
+        let mut seen = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(id) = queue.pop_front() {
+            let node = match self.node(id) {
+                Some(node) => node,
+                None => continue,
+            };
+            for (next, _) in node.links.iter() {
+                if *next == self.root.id {
+                    continue;
+                }
+                if seen.insert(*next) {
+                    visited.push(*next);
+                    queue.push_back(*next);
+                }
+            }
+        }
+        visited
+    }
+    #[doc = "The predecessor_ids function returns the id of every node (including"]
+    #[doc = "the root) with a link into the node with the provided id, along with"]
+    #[doc = "the portions of that link. It is a single pass over all links."]
+    pub(crate) fn predecessor_ids(&self, id: MarkovNodeID) -> Vec<(MarkovNodeID, u64)> {
+        std::iter::once(&self.root)
+            .chain(self.nodes.iter())
+            .filter_map(|node| node.links.get(id).map(|portions| (node.id, portions)))
+            .collect()
+    }
+    #[doc = "The remove_unreachable function removes every node returned by"]
+    #[doc = "[MarkovChain::unreachable] along with its links, and returns how"]
+    #[doc = "many nodes were removed."]
+    pub fn remove_unreachable(&mut self) -> usize {
+        let reachable: HashSet<MarkovNodeID> =
+            self.reachable_ids(self.root.id).into_iter().collect();
+        let ids: Vec<MarkovNodeID> = self
+            .nodes
+            .iter()
+            .filter(|node| !reachable.contains(&node.id))
+            .map(|node| node.id)
+            .collect();
+        self.remove_nodes(&ids);
+        ids.len()
+    }
+    #[doc = "The out_degree function returns the number of distinct words the"]
+    #[doc = "node links to, not counting a link back to the root (the end)."]
+    pub(crate) fn out_degree(&self, node: &MarkovNode<T>) -> usize {
+        node.links
+            .iter()
+            .filter(|(to, _)| **to != self.root.id)
+            .count()
+    }
+    #[doc = "The degree_distribution function returns a [Map] from out-degree"]
+    #[doc = "(the number of distinct words a word links to) to the number of"]
+    #[doc = "words with that out-degree, from the lowest out-degree up."]
+    pub fn degree_distribution(&self) -> Map<usize, usize> {
+        let mut degrees: Vec<usize> = self
+            .nodes
+            .iter()
+            .map(|node| self.out_degree(node))
+            .collect();
+        degrees.sort_unstable();
+        let mut out = Map::new();
+        for degree in degrees {
+            let count = out.get(degree).unwrap_or(0);
+            out.add(degree, count + 1);
+        }
+        out
+    }
+    #[doc = "The average_out_degree function returns the average number of"]
+    #[doc = "distinct words each word links to, or 0 for an empty chain."]
+    pub fn average_out_degree(&self) -> f64 {
+        if self.nodes.is_empty() {
+            return 0.0;
+        }
+        let links: usize = self.nodes.iter().map(|node| self.out_degree(node)).sum();
+        links as f64 / self.nodes.len() as f64
+    }
+    #[doc = "The max_out_degree function returns the most distinct words any"]
+    #[doc = "one word links to, or 0 for an empty chain."]
+    pub fn max_out_degree(&self) -> usize {
+        self.nodes
+            .iter()
+            .map(|node| self.out_degree(node))
+            .max()
+            .unwrap_or(0)
+    }
+    #[doc = "The min_out_degree function returns the fewest distinct words any"]
+    #[doc = "one word links to, or 0 for an empty chain."]
+    pub fn min_out_degree(&self) -> usize {
+        self.nodes
+            .iter()
+            .map(|node| self.out_degree(node))
+            .min()
+            .unwrap_or(0)
+    }
+    #[doc = "The median_out_degree function returns the median number of"]
+    #[doc = "distinct words each word links to (the mean of the middle two for an"]
+    #[doc = "even number of words), or 0 for an empty chain."]
+    pub fn median_out_degree(&self) -> f64 {
+        let mut degrees: Vec<usize> = self
+            .nodes
+            .iter()
+            .map(|node| self.out_degree(node))
+            .collect();
+        if degrees.is_empty() {
+            return 0.0;
+        }
+        degrees.sort_unstable();
+        let middle = degrees.len() / 2;
+        if degrees.len() % 2 == 1 {
+            degrees[middle] as f64
+        } else {
+            (degrees[middle - 1] + degrees[middle]) as f64 / 2.0
+        }
+    }
+    #[doc = "The node_entropy function returns the Shannon entropy (in bits) of"]
+    #[doc = "the provided word's successors, or [None] if the word isn't in the"]
+    #[doc = "chain. A word with one successor (or none) has an entropy of 0, and"]
+    #[doc = "a word with n equally likely successors has an entropy of log2(n)."]
+    pub fn node_entropy<Q>(&self, word: &Q) -> Option<f64>
+    where
+        T: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.find_token(word).map(|node| entropy(&node.links))
+    }
+    #[doc = "The average_entropy function returns the entropy of every word"]
+    #[doc = "averaged by how often generation visits it, i.e. weighted by the"]
+    #[doc = "portions of the links into it. An empty chain has an entropy of 0."]
+    pub fn average_entropy(&self) -> f64 {
+        let mut incoming: HashMap<MarkovNodeID, u64> = HashMap::new();
+        for node in std::iter::once(&self.root).chain(self.nodes.iter()) {
+            for (to, portions) in node.links.iter() {
+                if *to != self.root.id {
+                    *incoming.entry(*to).or_default() += portions;
+                }
+            }
+        }
+        let total: u64 = incoming.values().sum();
+        if total == 0 {
+            return 0.0;
+        }
+        self.nodes
+            .iter()
+            .map(|node| {
+                let weight = incoming.get(&node.id).copied().unwrap_or(0) as f64;
+                weight * entropy(&node.links)
+            })
+            .sum::<f64>()
+            / total as f64
+    }
+}
+
+impl MarkovChain {
+    #[doc = "The predecessors function returns every word that links into the"]
+    #[doc = "provided word, along with the portions of that link. Links from the"]
+    #[doc = "root are left out, and an unknown word has no predecessors."]
+    pub fn predecessors(&self, word: &str) -> Vec<(String, u64)> {
+        let id = match self.find_node(word) {
+            Some(node) => node.id,
+            None => return Vec::new(),
+        };
+        self.predecessor_ids(id)
+            .into_iter()
+            .filter(|(from, _)| *from != self.root.id)
+            .filter_map(|(from, portions)| {
+                self.node(from).map(|node| (node.data.clone(), portions))
+            })
+            .collect()
+    }
+    #[doc = "The describe_word function returns a small table of the provided"]
+    #[doc = "word's transitions for debugging: the word with the portions of the"]
+    #[doc = "links into and out of it, then each word it links to with the"]
+    #[doc = "portions and the probability of that link, most portions first. The"]
+    #[doc = "end of generation is shown as \"(end)\" and a word with no links as"]
+    #[doc = "\"(dead end)\". It returns [None] if the word was never trained."]
+    #[doc = ""]
+    #[doc = "```"]
+    #[doc = "use markov_chain_rs::types::MarkovChain;"]
+    #[doc = ""]
+    #[doc = "let mut chain = MarkovChain::new();"]
+    #[doc = "chain.train_text(\"the cat saw the dog. the cat ran.\".to_string());"]
+    #[doc = "assert_eq!("]
+    #[doc = "    chain.describe_word(\"the\").unwrap(),"]
+    #[doc = "    \"the (in: 3, out: 3)\\n  cat 2  66.7%\\n  dog 1  33.3%\","]
+    #[doc = ");"]
+    #[doc = "assert_eq!("]
+    #[doc = "    chain.describe_word(\"ran\").unwrap(),"]
+    #[doc = "    \"ran (in: 1, out: 1)\\n  (end) 1 100.0%\","]
+    #[doc = ");"]
+    #[doc = "```"]
+    pub fn describe_word(&self, word: &str) -> Option<String> {
+        let node = self.find_node(word)?;
+        let incoming: u64 = self
+            .predecessor_ids(node.id)
+            .iter()
+            .map(|(_, portions)| *portions)
+            .sum();
+        let outgoing: u64 = node.links.iter().map(|(_, portions)| *portions).sum();
+        let mut out = format!("{} (in: {incoming}, out: {outgoing})", node.data);
+        if node.links.is_empty() {
+            out.push_str("\n  (dead end)");
+            return Some(out);
+        }
+        let distribution = successor_distribution(self, node);
+        let mut rows: Vec<(&str, u64, f64)> = node
+            .links
+            .iter()
+            .filter_map(|(to, portions)| {
+                let to = self.node(*to)?;
+                let label = if to.id == self.root.id {
+                    "(end)"
+                } else {
+                    to.data.as_str()
+                };
+                let probability = distribution.get(to.data.as_str()).copied()?;
+                Some((label, *portions, probability))
+            })
+            .collect();
+        rows.sort_by_key(|(_, portions, _)| std::cmp::Reverse(*portions));
+        let word_width = rows.iter().map(|(label, ..)| label.chars().count()).max();
+        let count_width = rows
+            .iter()
+            .map(|(_, portions, _)| portions.to_string().len())
+            .max();
+        for (label, portions, probability) in rows {
+            out.push_str(&format!(
+                "\n  {label:<word_width$} {portions:>count_width$} {:>5.1}%",
+                probability * 100.0,
+                word_width = word_width.unwrap_or(0),
+                count_width = count_width.unwrap_or(0),
+            ));
+        }
+        Some(out)
+    }
+    #[doc = "The dead_ends function returns the words that don't link to any"]
+    #[doc = "other word, which are the words generation always stops at: either"]
+    #[doc = "their only link is the end of a sentence, or they have no links at"]
+    #[doc = "all. A chain trained on \"x y\" has \"y\" as a dead end."]
+    pub fn dead_ends(&self) -> Vec<&str> {
+        self.nodes
+            .iter()
+            .filter(|node| self.out_degree(node) == 0)
+            .map(|node| node.data.as_str())
+            .collect()
+    }
+    #[doc = "The nodes_with_out_degree function returns the words that link to"]
+    #[doc = "exactly degree distinct words (not counting the end)."]
+    pub fn nodes_with_out_degree(&self, degree: usize) -> Vec<&str> {
+        self.nodes
+            .iter()
+            .filter(|node| self.out_degree(node) == degree)
+            .map(|node| node.data.as_str())
+            .collect()
+    }
+    #[doc = "The unreachable function returns the words that can't be reached"]
+    #[doc = "from the root by following links, so generation can never produce them."]
+    pub fn unreachable(&self) -> Vec<&str> {
+        let reachable: HashSet<MarkovNodeID> =
+            self.reachable_ids(self.root.id).into_iter().collect();
+        self.nodes
+            .iter()
+            .filter(|node| !reachable.contains(&node.id))
+            .map(|node| node.data.as_str())
+            .collect()
+    }
+    #[doc = "The word_frequencies function returns every word with the number"]
+    #[doc = "of times it was trained as the start of a link (the sum of its"]
+    #[doc = "links' portions), most frequent first. Words with the same frequency"]
+    #[doc = "stay in the order they were first trained."]
+    pub fn word_frequencies(&self) -> Vec<(String, u64)> {
+        let mut out: Vec<(String, u64)> = self
+            .nodes
+            .iter()
+            .map(|node| (node.data.clone(), node.links.iter().map(|(_, p)| *p).sum()))
+            .collect();
+        out.sort_by_key(|(_, frequency)| std::cmp::Reverse(*frequency));
+        out
+    }
+    #[doc = "The most_common_words function returns the first n entries of"]
+    #[doc = "[MarkovChain::word_frequencies]."]
+    pub fn most_common_words(&self, n: usize) -> Vec<(String, u64)> {
+        let mut out = self.word_frequencies();
+        out.truncate(n);
+        out
+    }
+    #[doc = "The most_common_transitions function returns the n links with the"]
+    #[doc = "most portions as (from word, to word, portions), most portions first."]
+    #[doc = "Links into and out of the root are left out."]
+    pub fn most_common_transitions(&self, n: usize) -> Vec<(String, String, u64)> {
+        let mut out = Vec::new();
+        for node in &self.nodes {
+            for (to, portions) in node.links.iter() {
+                if *to == self.root.id {
+                    continue;
+                }
+                if let Some(to) = self.node(*to) {
+                    out.push((node.data.clone(), to.data.clone(), *portions));
+                }
+            }
+        }
+        out.sort_by_key(|(_, _, portions)| std::cmp::Reverse(*portions));
+        out.truncate(n);
+        out
+    }
+    #[doc = "The shortest_path function returns the fewest-hops sequence of words"]
+    #[doc = "leading from one word to the other by following links, including both"]
+    #[doc = "ends. It returns [None] if either word isn't in the chain or there is"]
+    #[doc = "no path between them."]
+    pub fn shortest_path(&self, from: &str, to: &str) -> Option<Vec<String>> {
+        let start = self.find_node(from)?.id;
+        let goal = self.find_node(to)?.id;
+        let mut parents: HashMap<MarkovNodeID, MarkovNodeID> = HashMap::new();
+        let mut queue = VecDeque::from([start]);
+        let mut found = start == goal;
+        while let Some(id) = queue.pop_front() {
+            if found {
+                break;
+            }
+            let node = match self.node(id) {
+                Some(node) => node,
+                None => continue,
+            };
+            for (next, _) in node.links.iter() {
+                if *next == start || *next == self.root.id || parents.contains_key(next) {
+                    continue;
+                }
+                parents.insert(*next, id);
+                if *next == goal {
+                    found = true;
+                    break;
+                }
+                queue.push_back(*next);
+            }
+        }
+        if !found {
+            return None;
+        }
+        let mut path = vec![goal];
+        let mut current = goal;
+        while current != start {
+            current = parents[&current];
+            path.push(current);
+        }
+        path.reverse();
+        Some(
+            path.into_iter()
+                .filter_map(|id| self.node(id).map(|node| node.data.clone()))
+                .collect(),
+        )
+    }
+    #[doc = "The reachable_from function returns every word that can be reached"]
+    #[doc = "from the provided word by following one or more links. The word"]
+    #[doc = "itself is only included if a cycle leads back to it, and an unknown"]
+    #[doc = "word reaches nothing."]
+    pub fn reachable_from(&self, word: &str) -> Vec<String> {
+        let start = match self.find_node(word) {
+            Some(node) => node.id,
+            None => return Vec::new(),
+        };
+        let reachable = self.reachable_ids(start);
+        let cycles_back = reachable
+            .iter()
+            .filter_map(|id| self.node(*id))
+            .any(|node| node.links.has(start));
+        reachable
+            .into_iter()
+            .filter(|id| cycles_back || *id != start)
+            .filter_map(|id| self.node(id).map(|node| node.data.clone()))
+            .collect()
+    }
+    #[doc = "The copy_subgraph function returns a new chain (with this chain's"]
+    #[doc = "configuration) holding the seed word and every word up to max_depth"]
+    #[doc = "links away from it, along with the links between them and their"]
+    #[doc = "links to the end. The new root links to the seed with the portions"]
+    #[doc = "this chain's root does, or 1 if no sentence started with the seed, so"]
+    #[doc = "generating from it always starts at the seed. An unknown seed gives"]
+    #[doc = "[MarkovError::WordNotFound]."]
+    pub fn copy_subgraph(&self, seed: &str, max_depth: usize) -> Result<MarkovChain, MarkovError> {
+        let start = self
+            .find_node(seed)
+            .ok_or_else(|| MarkovError::WordNotFound(seed.to_string()))?;
+        let mut visited = vec![start.id];
+        let mut depths = HashMap::from([(start.id, 0)]);
+        let mut queue = VecDeque::from([start.id]);
+        while let Some(id) = queue.pop_front() {
+            let depth = depths[&id];
+            if depth == max_depth {
+                continue;
+            }
+            let node = match self.node(id) {
+                Some(node) => node,
+                None => continue,
+            };
+            for (next, _) in node.links.iter() {
+                if *next != self.root.id && !depths.contains_key(next) {
+                    depths.insert(*next, depth + 1);
+                    visited.push(*next);
+                    queue.push_back(*next);
+                }
+            }
+        }
+        let mut out = self.empty_like();
+        let out_root = out.root.id;
+        let ids: HashMap<MarkovNodeID, MarkovNodeID> = visited
+            .iter()
+            .filter_map(|id| self.node(*id))
+            .map(|node| (node.id, out.find_or_create_node(node.data.as_str())))
+            .chain(std::iter::once((self.root.id, out_root)))
+            .collect();
+        let seed_portions = self.root.links.get(start.id).unwrap_or(1);
+        out.add_transition(out_root, ids[&start.id], seed_portions);
+        for id in &visited {
+            let node = match self.node(*id) {
+                Some(node) => node,
+                None => continue,
+            };
+            for (to, portions) in node.links.iter() {
+                if let Some(to) = ids.get(to) {
+                    out.add_transition(ids[id], *to, *portions);
+                }
+            }
+        }
+        Ok(out)
+    }
+    #[doc = "The components function splits the words into weakly-connected"]
+    #[doc = "components, i.e. groups of words connected by links in either"]
+    #[doc = "direction. The root is left out, since it would connect everything."]
+    pub fn components(&self) -> Vec<Vec<String>> {
+        let mut neighbours: HashMap<MarkovNodeID, Vec<MarkovNodeID>> = HashMap::new();
+        for node in &self.nodes {
+            for (to, _) in node.links.iter() {
+                if *to == self.root.id {
+                    continue;
+                }
+                neighbours.entry(node.id).or_default().push(*to);
+                neighbours.entry(*to).or_default().push(node.id);
+            }
+        }
+        let mut seen = HashSet::new();
+        let mut out = Vec::new();
+        for node in &self.nodes {
+            if !seen.insert(node.id) {
+                continue;
+            }
+            let mut component = Vec::new();
+            let mut stack = vec![node.id];
+            while let Some(id) = stack.pop() {
+                component.push(id);
+                for next in neighbours.get(&id).into_iter().flatten() {
+                    if seen.insert(*next) {
+                        stack.push(*next);
+                    }
+                }
+            }
+            component.sort_unstable();
+            out.push(
+                component
+                    .into_iter()
+                    .filter_map(|id| self.node(id).map(|node| node.data.clone()))
+                    .collect(),
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::MarkovError;
+    use crate::types::MarkovChain;
+
+    #[test]
+    fn describing_words() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a b a b a c".to_string());
+        chain.train_word("c", "sink");
+        assert_eq!(
+            chain.describe_word("A").unwrap(),
+            "a (in: 3, out: 3)\n  b 2  66.7%\n  c 1  33.3%"
+        );
+        assert_eq!(
+            chain.describe_word("sink").unwrap(),
+            "sink (in: 1, out: 0)\n  (dead end)"
+        );
+        assert_eq!(chain.describe_word("missing"), None);
+    }
+
+    #[test]
+    fn dead_ends_and_unreachable_words() {
+        let mut chain = MarkovChain::new();
+        assert!(chain.dead_ends().is_empty());
+        chain.train_text("x y. x z x".to_string());
+        // "y" only ends a sentence, "z" goes on to "x"
+        assert_eq!(chain.dead_ends(), ["y"]);
+        assert!(chain.unreachable().is_empty());
+
+        // "p" and "q" are linked, but no sentence starts with them
+        chain.train_word("p", "q");
+        assert_eq!(chain.dead_ends(), ["y", "q"]);
+        assert_eq!(chain.unreachable(), ["p", "q"]);
+        assert_eq!(chain.remove_unreachable(), 2);
+        assert!(chain.unreachable().is_empty());
+        assert_eq!(chain.dead_ends(), ["y"]);
+        assert!(chain.is_valid());
+        assert_eq!(chain.remove_unreachable(), 0);
+    }
+
+    #[test]
+    fn shortest_paths() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a b c d. a d. e f".to_string());
+        assert_eq!(
+            chain.shortest_path("a", "d").unwrap(),
+            ["a", "d"].map(String::from)
+        );
+        assert_eq!(
+            chain.shortest_path("B", "d").unwrap(),
+            ["b", "c", "d"].map(String::from)
+        );
+        // from == to
+        assert_eq!(chain.shortest_path("c", "c").unwrap(), ["c"]);
+        // no path
+        assert_eq!(chain.shortest_path("d", "a"), None);
+        assert_eq!(chain.shortest_path("a", "f"), None);
+        // absent words
+        assert_eq!(chain.shortest_path("a", "missing"), None);
+        assert_eq!(chain.shortest_path("missing", "a"), None);
+    }
+
+    #[test]
+    fn two_component_chains() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a b c. x y x".to_string());
+        assert_eq!(chain.components(), [vec!["a", "b", "c"], vec!["x", "y"]]);
+        assert_eq!(chain.reachable_from("a"), ["b", "c"]);
+        assert_eq!(chain.reachable_from("c"), Vec::<String>::new());
+        // "x" cycles back through "y", so it reaches itself
+        assert_eq!(chain.reachable_from("x"), ["x", "y"]);
+        assert_eq!(chain.reachable_from("y"), ["y", "x"]);
+        assert!(chain.reachable_from("missing").is_empty());
+
+        // a link in either direction joins the components
+        chain.train_word("y", "b");
+        assert_eq!(chain.components().len(), 1);
+        assert!(MarkovChain::new().components().is_empty());
+    }
+
+    #[test]
+    fn predecessors_with_their_portions() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("the cat. a cat. the cat. big cat".to_string());
+        assert_eq!(
+            chain.predecessors("Cat"),
+            [
+                ("the".to_string(), 2),
+                ("a".to_string(), 1),
+                ("big".to_string(), 1)
+            ]
+        );
+        // only the root links into "the", and that link is left out
+        assert!(chain.predecessors("the").is_empty());
+        assert!(chain.predecessors("missing").is_empty());
+    }
+
+    #[test]
+    fn word_frequencies_count_outgoing_portions() {
+        let mut chain = MarkovChain::new();
+        assert!(chain.word_frequencies().is_empty());
+        // "the" starts 3 links, "cat" 2 (one to the end), the rest 1
+        chain.train_text("the cat sat. the cat. the dog".to_string());
+        let frequencies = chain.word_frequencies();
+        assert_eq!(
+            frequencies,
+            [
+                ("the".to_string(), 3),
+                ("cat".to_string(), 2),
+                ("sat".to_string(), 1),
+                ("dog".to_string(), 1)
+            ]
+        );
+        // ties keep the order the words were first trained in
+        assert_eq!(
+            chain.most_common_words(3),
+            [
+                ("the".to_string(), 3),
+                ("cat".to_string(), 2),
+                ("sat".to_string(), 1)
+            ]
+        );
+        assert_eq!(chain.most_common_words(10), frequencies);
+        assert!(chain.most_common_words(0).is_empty());
+    }
+
+    #[test]
+    fn subgraphs_stop_at_max_depth() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a b c d. x b y. b z".to_string());
+        let sub = chain.copy_subgraph("B", 1).unwrap();
+        let words: Vec<&str> = sub.nodes.iter().map(|node| node.data.as_str()).collect();
+        assert_eq!(words, ["b", "c", "y", "z"]);
+        assert_eq!(sub.get_probability("b", "c"), 1.0 / 3.0);
+        assert_eq!(sub.find_node("y").unwrap().links.get(sub.root.id), Some(1));
+        assert!(sub.find_node("c").unwrap().links.is_empty());
+        assert_eq!(sub.root.links.clone().into_vec(), [(1, 1)]);
+        assert!(sub.is_valid());
+
+        let sub = chain.copy_subgraph("c", 5).unwrap();
+        let words: Vec<&str> = sub.nodes.iter().map(|node| node.data.as_str()).collect();
+        assert_eq!(words, ["c", "d"]);
+        assert_eq!(sub.root.links.get(1), Some(1));
+        assert_eq!(sub.generate(10).unwrap(), ["c", "d"]);
+        assert_eq!(chain.copy_subgraph("a", 0).unwrap().nodes.len(), 1);
+        assert_eq!(
+            chain.copy_subgraph("q", 1).unwrap_err(),
+            MarkovError::WordNotFound("q".to_string())
+        );
+    }
+
+    #[test]
+    fn entropy_of_single_successor_is_zero() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a b a b".to_string());
+        assert_eq!(chain.node_entropy("a"), Some(0.0));
+        assert_eq!(chain.node_entropy("missing"), None);
+    }
+
+    #[test]
+    fn entropy_of_uniform_successors_is_log2_n() {
+        let mut chain = MarkovChain::new();
+        chain.train_word("a", "b");
+        chain.train_word("a", "c");
+        chain.train_word("a", "d");
+        chain.train_word("a", "e");
+        let entropy = chain.node_entropy("a").unwrap();
+        assert!((entropy - 2.0).abs() < 1e-12);
+        assert_eq!(chain.node_entropy("b"), Some(0.0));
+    }
+
+    #[test]
+    fn entropy_uses_probabilities_not_counts() {
+        let mut chain = MarkovChain::new();
+        for _ in 0..5 {
+            chain.train_word("a", "b");
+            chain.train_word("a", "c");
+        }
+        assert!((chain.node_entropy("a").unwrap() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn average_entropy_is_weighted_by_incoming_portions() {
+        let mut chain = MarkovChain::new();
+        assert_eq!(chain.average_entropy(), 0.0);
+        // root -> a (1), a -> b (1), a -> c (1): only "a" has entropy (1 bit),
+        // and it is visited once out of three incoming portions
+        chain.train_text("a b".to_string());
+        chain.train_word("a", "c");
+        assert!((chain.average_entropy() - 1.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn out_degree_statistics() {
+        let mut chain = MarkovChain::new();
+        assert_eq!(chain.median_out_degree(), 0.0);
+        assert_eq!(chain.min_out_degree(), 0);
+        // a -> b, c, d; b -> c; c and d only end
+        chain.train_text("a b c".to_string());
+        chain.train_word("a", "c");
+        chain.train_word("a", "d");
+        assert_eq!(chain.min_out_degree(), 0);
+        assert_eq!(chain.max_out_degree(), 3);
+        assert_eq!(chain.median_out_degree(), 0.5);
+        assert_eq!(chain.average_out_degree(), 1.0);
+        assert_eq!(chain.nodes_with_out_degree(0), ["c", "d"]);
+        assert_eq!(chain.nodes_with_out_degree(3), ["a"]);
+        assert!(chain.nodes_with_out_degree(2).is_empty());
+        assert_eq!(
+            chain.degree_distribution().into_vec(),
+            [(0, 2), (1, 1), (3, 1)]
+        );
+        assert!(MarkovChain::new().degree_distribution().is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MarkovError {
+    EmptyChain, // the chain has no links out of the root, so there is nothing to generate
+    WordNotFound(String), // the word was never trained into the chain
+    InvalidConfig(String), // the chain was configured with options that make no sense
+    Io(String), // reading the training text failed, holds the error message
+    FileNotFound(PathBuf), // the file to train from doesn't exist
+    PermissionDenied(PathBuf), // the file to train from can't be read
+    Parse(String), // some text (like a regex) couldn't be parsed, holds the reason
+    Corrupt(ValidationError), // the chain's internals are inconsistent, see MarkovChain::validate
+    GenerationFailed { attempts: usize }, // generation gave up after this many attempts without an acceptable text
+}
+
+impl fmt::Display for MarkovError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarkovError::EmptyChain => write!(f, "the markov chain has not been trained"),
+            MarkovError::WordNotFound(word) => {
+                write!(f, "the word {word:?} is not in the markov chain")
+            }
+            MarkovError::InvalidConfig(reason) => {
+                write!(f, "invalid markov chain configuration: {reason}")
+            }
+            MarkovError::Io(reason) => write!(f, "failed to read training text: {reason}"),
+            MarkovError::FileNotFound(path) => write!(f, "{} does not exist", path.display()),
+            MarkovError::PermissionDenied(path) => {
+                write!(f, "not allowed to read {}", path.display())
+            }
+            MarkovError::Parse(reason) => write!(f, "failed to parse: {reason}"),
+            MarkovError::Corrupt(err) => write!(f, "the markov chain is corrupt: {err}"),
+            MarkovError::GenerationFailed { attempts } => {
+                write!(f, "gave up generating after {attempts} attempts")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MarkovError {}
+
+impl From<std::io::Error> for MarkovError {
+    fn from(err: std::io::Error) -> MarkovError {
+        MarkovError::Io(err.to_string())
+    }
+}
+
+#[doc = "The ValidationError enum lists the ways the internals of a"]
+#[doc = "[crate::types::MarkovChain] can be inconsistent, as found by"]
+#[doc = "[crate::types::MarkovChain::validate]."]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    NodesMapLength { nodes: usize, nodes_map: usize }, // nodes_map has a different number of entries than nodes
+    NodesMapMismatch(usize), // the nodes_map entry for this id doesn't point at the node with this id
+    MissingFromNodesMap(usize), // the node with this id has no nodes_map entry
+    DuplicateId(usize),      // more than one node has this id
+    DuplicateWord(String),   // more than one node holds this word
+    WordIndexMismatch(String), // the word index doesn't point at the node holding this word
+    DanglingLink { from: usize, to: usize }, // a link points to an id no node has
+    ZeroPortionLink { from: usize, to: usize }, // a link has no portions, so it can never be picked
+    DuplicateLink { from: usize, to: usize }, // a node has more than one link to the same id
+    AllPortionsMismatch { stored: u64, actual: u64 }, // all_portions isn't the sum of every link's portions
+    NewestIdTooLow { newest_id: usize, max_id: usize }, // newest_id is lower than an id in use, so it would be handed out again
+    ContinuationCountMismatch { id: usize, stored: u64, actual: u64 }, // the continuation count of this id isn't the number of distinct nodes linking to it
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::NodesMapLength { nodes, nodes_map } => write!(
+                f,
+                "nodes_map has {nodes_map} entries but there are {nodes} nodes"
+            ),
+            ValidationError::NodesMapMismatch(id) => {
+                write!(
+                    f,
+                    "the nodes_map entry for id {id} points at the wrong node"
+                )
+            }
+            ValidationError::MissingFromNodesMap(id) => {
+                write!(f, "the node with id {id} is missing from nodes_map")
+            }
+            ValidationError::DuplicateId(id) => write!(f, "more than one node has id {id}"),
+            ValidationError::DuplicateWord(word) => {
+                write!(f, "more than one node holds the word {word:?}")
+            }
+            ValidationError::WordIndexMismatch(word) => {
+                write!(
+                    f,
+                    "the word index doesn't point at the node holding {word:?}"
+                )
+            }
+            ValidationError::DanglingLink { from, to } => {
+                write!(f, "node {from} links to id {to}, which doesn't exist")
+            }
+            ValidationError::ZeroPortionLink { from, to } => {
+                write!(f, "the link from node {from} to node {to} has no portions")
+            }
+            ValidationError::DuplicateLink { from, to } => {
+                write!(f, "node {from} links to node {to} more than once")
+            }
+            ValidationError::AllPortionsMismatch { stored, actual } => write!(
+                f,
+                "all_portions is {stored} but the links add up to {actual}"
+            ),
+            ValidationError::NewestIdTooLow { newest_id, max_id } => write!(
+                f,
+                "newest_id is {newest_id} but id {max_id} is already in use"
+            ),
+            ValidationError::ContinuationCountMismatch { id, stored, actual } => write!(
+                f,
+                "the continuation count of node {id} is {stored} but {actual} nodes link to it"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+#[doc = "The MapError enum lists the ways an operation on a"]
+#[doc = "[crate::types::Map] can fail."]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MapError {
+    KeyNotFound, // one of the keys isn't in the map
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::KeyNotFound => write!(f, "the key is not in the map"),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
@@ -0,0 +1,574 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::error::MarkovError;
+use crate::types::{create_markov_chain_with_order, MarkovChain, MarkovNode, MarkovNodeID};
+
+#[doc = "The json_string function writes the text as a quoted JSON string,"]
+#[doc = "escaping quotes, backslashes and control characters."]
+fn json_string(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+impl MarkovChain {
+    #[doc = "The to_json function returns the chain as a JSON object with its"]
+    #[doc = "order, [MarkovChain::total_tokens_trained] and every node (the root"]
+    #[doc = "first, with an empty word), each node having its id, word and links"]
+    #[doc = "as [to, portions] pairs:"]
+    #[doc = "`{\"order\":1,\"total_tokens_trained\":2,\"nodes\":[{\"id\":0,\"word\":\"\",\"links\":[[1,1]]},...]}`."]
+    pub fn to_json(&self) -> String {
+        let mut out = format!(
+            "{{\"order\":{},\"total_tokens_trained\":{},\"nodes\":[",
+            self.order(),
+            self.total_tokens_trained()
+        );
+        let nodes: Vec<&MarkovNode> = std::iter::once(&self.root).chain(&self.nodes).collect();
+        for (i, node) in nodes.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write!(out, "{{\"id\":{},\"word\":", node.id).unwrap();
+            json_string(&mut out, &node.data);
+            out.push_str(",\"links\":[");
+            for (j, (to, portions)) in node.links.iter().enumerate() {
+                if j > 0 {
+                    out.push(',');
+                }
+                write!(out, "[{to},{portions}]").unwrap();
+            }
+            out.push_str("]}");
+        }
+        out.push_str("]}");
+        out
+    }
+
+    #[doc = "The from_json function loads a chain written by"]
+    #[doc = "[MarkovChain::to_json], with the same order, words, links and"]
+    #[doc = "[MarkovChain::total_tokens_trained]. Node ids are handed out again in"]
+    #[doc = "the order the nodes are listed, so they only match the JSON for a"]
+    #[doc = "chain without gaps in its ids (see [MarkovChain::compact]). The"]
+    #[doc = "configuration (smoothing, tokenizer and so on) isn't part of the JSON,"]
+    #[doc = "so the loaded chain has the default one. The lower-order chains a"]
+    #[doc = "higher-order chain backs off to are rebuilt from its links. JSON that"]
+    #[doc = "isn't in this format gives [MarkovError::Parse]."]
+    pub fn from_json(json: &str) -> Result<MarkovChain, MarkovError> {
+        let value = parse_json(json)?;
+        let fields = json_object(&value, "the chain")?;
+        let order = json_u64(json_field(fields, "order")?, "order")?;
+        let mut chain = create_markov_chain_with_order(order as usize)?;
+        let total_tokens_trained = json_u64(
+            json_field(fields, "total_tokens_trained")?,
+            "total_tokens_trained",
+        )?;
+        let nodes = json_array(json_field(fields, "nodes")?, "nodes")?;
+        let mut ids: HashMap<u64, MarkovNodeID> = HashMap::new();
+        let mut links = Vec::new();
+        for node in nodes {
+            let node = json_object(node, "a node")?;
+            let id = json_u64(json_field(node, "id")?, "a node's id")?;
+            let word = match json_field(node, "word")? {
+                Json::String(word) => word,
+                _ => {
+                    return Err(MarkovError::Parse(
+                        "a node's word must be a string".to_string(),
+                    ))
+                }
+            };
+            let new_id = match (id, word.is_empty()) {
+                (0, true) => chain.root.id,
+                (0, false) => {
+                    return Err(MarkovError::Parse(
+                        "node 0 is the root and can't have a word".to_string(),
+                    ))
+                }
+                (_, true) => {
+                    return Err(MarkovError::Parse(format!("node {id} has an empty word")))
+                }
+                (_, false) => chain.find_or_create_node(word.as_str()),
+            };
+            if ids.insert(id, new_id).is_some() {
+                return Err(MarkovError::Parse(format!(
+                    "more than one node has id {id}"
+                )));
+            }
+            links.push((new_id, json_array(json_field(node, "links")?, "links")?));
+        }
+        for (from, node_links) in links {
+            for link in node_links {
+                let (to, portions) = match json_array(link, "a link")? {
+                    [to, portions] => (
+                        json_u64(to, "a link's id")?,
+                        json_u64(portions, "a link's portions")?,
+                    ),
+                    _ => {
+                        return Err(MarkovError::Parse(
+                            "a link must be a [to, portions] pair".to_string(),
+                        ))
+                    }
+                };
+                let to = ids
+                    .get(&to)
+                    .ok_or_else(|| MarkovError::Parse(format!("a link goes to unknown id {to}")))?;
+                if portions > 0 {
+                    chain.add_transition(from, *to, portions);
+                }
+            }
+        }
+        chain.total_tokens_trained = total_tokens_trained;
+        Ok(chain)
+    }
+
+    #[doc = "The to_mermaid function returns the chain as a Mermaid `graph LR`"]
+    #[doc = "diagram, with every node labeled with its word (the root is labeled"]
+    #[doc = "root) and every edge with its probability, rounded to 2 decimal"]
+    #[doc = "places. max_edges keeps only that many of the highest-weight edges."]
+    pub fn to_mermaid(&self, max_edges: Option<usize>) -> String {
+        let nodes: Vec<&MarkovNode> = std::iter::once(&self.root).chain(&self.nodes).collect();
+        let mut edges: Vec<(&MarkovNode, MarkovNodeID, u64, u64)> = Vec::new();
+        for node in &nodes {
+            let total: u64 = node.links.iter().map(|(_, portions)| portions).sum();
+            for (to, portions) in node.links.iter() {
+                edges.push((node, *to, *portions, total));
+            }
+        }
+        edges.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.id.cmp(&b.0.id)).then(a.1.cmp(&b.1)));
+        edges.truncate(max_edges.unwrap_or(edges.len()));
+        let mut out = String::from("graph LR\n");
+        for node in &nodes {
+            let label = match node.id == self.root.id {
+                true => "root",
+                false => node.data.as_str(),
+            };
+            writeln!(out, "    n{}[\"{}\"]", node.id, mermaid_label(label)).unwrap();
+        }
+        for (from, to, portions, total) in edges {
+            let probability = portions as f64 / total as f64;
+            writeln!(out, "    n{} -->|{probability:.2}| n{to}", from.id).unwrap();
+        }
+        out
+    }
+
+    #[doc = "The to_transition_pairs function returns every link as a (from word,"]
+    #[doc = "to word, probability) triple, where the probability is the link's"]
+    #[doc = "share of the from word's portions. The root is the empty word, so"]
+    #[doc = "links from it start a sentence and links to it end one. The triples"]
+    #[doc = "are sorted by from word, then by probability (highest first), then"]
+    #[doc = "by to word."]
+    pub fn to_transition_pairs(&self) -> Vec<(String, String, f64)> {
+        let mut out = Vec::new();
+        for node in std::iter::once(&self.root).chain(&self.nodes) {
+            let total: u64 = node.links.iter().map(|(_, portions)| portions).sum();
+            for (to, portions) in node.links.iter() {
+                if let Some(to) = self.node(*to) {
+                    let probability = *portions as f64 / total as f64;
+                    out.push((node.data.clone(), to.data.clone(), probability));
+                }
+            }
+        }
+        out.sort_by(|a, b| a.0.cmp(&b.0).then(b.2.total_cmp(&a.2)).then(a.1.cmp(&b.1)));
+        out
+    }
+}
+
+#[doc = "The Json enum is a parsed JSON value, as read by [parse_json]."]
+#[doc = "Numbers are kept as their text, since the chain's are all integers,"]
+#[doc = "and true, false and null as Literal, since it has none of them."]
+enum Json {
+    Literal,
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+#[doc = "The MAX_JSON_DEPTH constant is how deeply arrays and objects can be"]
+#[doc = "nested in JSON read by [MarkovChain::from_json]. The format only"]
+#[doc = "needs 4 levels, the limit keeps bad input from overflowing the stack."]
+const MAX_JSON_DEPTH: usize = 32;
+
+#[doc = "The JsonParser struct reads a [Json] value from text, one character"]
+#[doc = "at a time from pos, with depth arrays and objects open around it."]
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self, what: &str) -> MarkovError {
+        MarkovError::Parse(format!("{what} at byte {} of the JSON", self.pos))
+    }
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+    fn expect(&mut self, expected: char) -> Result<(), MarkovError> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(self.error(&format!("expected {expected:?}"))),
+        }
+    }
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, MarkovError> {
+        if !self.text[self.pos..].starts_with(word) {
+            return Err(self.error("unexpected character"));
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+    fn value(&mut self) -> Result<Json, MarkovError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c @ ('{' | '[')) => {
+                if self.depth == MAX_JSON_DEPTH {
+                    return Err(self.error("nested too deeply"));
+                }
+                self.depth += 1;
+                let value = if c == '{' {
+                    self.object()
+                } else {
+                    self.array()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('t') => self.keyword("true", Json::Literal),
+            Some('f') => self.keyword("false", Json::Literal),
+            Some('n') => self.keyword("null", Json::Literal),
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c))
+                {
+                    self.pos += 1;
+                }
+                Ok(Json::Number(self.text[start..self.pos].to_string()))
+            }
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+    fn array(&mut self) -> Result<Json, MarkovError> {
+        self.expect('[')?;
+        let mut out = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Json::Array(out));
+        }
+        loop {
+            out.push(self.value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Json::Array(out)),
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+    fn object(&mut self) -> Result<Json, MarkovError> {
+        self.expect('{')?;
+        let mut out = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Json::Object(out));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            out.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Json::Object(out)),
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+    fn hex4(&mut self) -> Result<u32, MarkovError> {
+        let digits = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()));
+        let code = digits.and_then(|digits| u32::from_str_radix(digits, 16).ok());
+        match code {
+            Some(code) => {
+                self.pos += 4;
+                Ok(code)
+            }
+            None => Err(self.error("expected 4 hex digits")),
+        }
+    }
+    fn string(&mut self) -> Result<String, MarkovError> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(out),
+                Some('\\') => {
+                    let c = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let mut code = self.hex4()?;
+                            // a high surrogate is followed by the low one
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.text[self.pos..].starts_with("\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(self.error("expected a low surrogate"));
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            char::from_u32(code)
+                                .ok_or_else(|| self.error("invalid unicode escape"))?
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    out.push(c);
+                }
+                Some(c) => out.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+}
+
+#[doc = "The parse_json function parses the whole text as one JSON value."]
+fn parse_json(text: &str) -> Result<Json, MarkovError> {
+    let mut parser = JsonParser {
+        text,
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos != text.len() {
+        return Err(parser.error("unexpected text after the value"));
+    }
+    Ok(value)
+}
+
+#[doc = "The json_field function returns the field of the JSON object with the"]
+#[doc = "provided name."]
+fn json_field<'a>(fields: &'a [(String, Json)], name: &str) -> Result<&'a Json, MarkovError> {
+    fields
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value)
+        .ok_or_else(|| MarkovError::Parse(format!("the JSON is missing {name:?}")))
+}
+
+#[doc = "The json_object function returns the fields of the JSON value, which"]
+#[doc = "must be an object."]
+fn json_object<'a>(value: &'a Json, what: &str) -> Result<&'a [(String, Json)], MarkovError> {
+    match value {
+        Json::Object(fields) => Ok(fields),
+        _ => Err(MarkovError::Parse(format!("{what} must be a JSON object"))),
+    }
+}
+
+#[doc = "The json_array function returns the items of the JSON value, which"]
+#[doc = "must be an array."]
+fn json_array<'a>(value: &'a Json, what: &str) -> Result<&'a [Json], MarkovError> {
+    match value {
+        Json::Array(items) => Ok(items),
+        _ => Err(MarkovError::Parse(format!("{what} must be a JSON array"))),
+    }
+}
+
+#[doc = "The json_u64 function returns the JSON value as a u64, which it must"]
+#[doc = "be a non-negative integer to fit in."]
+fn json_u64(value: &Json, what: &str) -> Result<u64, MarkovError> {
+    match value {
+        Json::Number(number) => number.parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| MarkovError::Parse(format!("{what} must be a non-negative integer")))
+}
+
+#[doc = "The mermaid_label function escapes the text for use inside a quoted"]
+#[doc = "Mermaid label, where quotes and angle brackets have to be entities."]
+fn mermaid_label(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("#quot;"),
+            '<' => out.push_str("#lt;"),
+            '>' => out.push_str("#gt;"),
+            '#' => out.push_str("#35;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_json, MAX_JSON_DEPTH};
+    use crate::error::MarkovError;
+    use crate::types::{create_markov_chain_with_order, MarkovChain};
+    use regex::Regex;
+
+    #[test]
+    fn json_lists_every_node() {
+        let mut chain = MarkovChain::new();
+        chain.train_word("say", "\"hi\"");
+        assert_eq!(
+            chain.to_json(),
+            concat!(
+                r#"{"order":1,"total_tokens_trained":2,"nodes":["#,
+                r#"{"id":0,"word":"","links":[]},"#,
+                r#"{"id":1,"word":"say","links":[[2,1]]},"#,
+                r#"{"id":2,"word":"\"hi\"","links":[]}]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("the cat sat. the \"dog\" ran\tfast. ünï 🐈".to_string());
+        chain.train_words_weighted(&["the", "cat"], 3);
+        let json = chain.to_json();
+        let loaded = MarkovChain::from_json(&json).unwrap();
+        assert_eq!(loaded.total_tokens_trained(), chain.total_tokens_trained());
+        assert_eq!(loaded.to_json(), json);
+        assert_eq!(loaded.to_transition_pairs(), chain.to_transition_pairs());
+        assert!(loaded.is_valid());
+
+        let mut chain = create_markov_chain_with_order(2).unwrap();
+        chain.train_text("a b c d".to_string());
+        let loaded = MarkovChain::from_json(&chain.to_json()).unwrap();
+        assert_eq!(loaded.order(), 2);
+        assert_eq!(loaded.to_json(), chain.to_json());
+
+        let loaded = MarkovChain::from_json(
+            r#" { "nodes" : [ {"word":"","id":0,"links":[[1,2]]},
+                {"id":1,"word":"\u00e9\ud83d\ude00","links":[]} ],
+                "order":1, "total_tokens_trained":7 } "#,
+        )
+        .unwrap();
+        assert!(loaded.find_node("é😀").is_some());
+        assert_eq!(loaded.total_tokens_trained(), 7);
+    }
+
+    #[test]
+    fn bad_json_is_a_parse_error() {
+        for json in [
+            "",
+            "{",
+            "[]",
+            r#"{"order":1,"nodes":[]}"#,
+            r#"{"order":1,"total_tokens_trained":-1,"nodes":[]}"#,
+            r#"{"order":1,"total_tokens_trained":0,"nodes":[{"id":1,"word":"a","links":[[2,1]]}]}"#,
+            r#"{"order":1,"total_tokens_trained":0,"nodes":[{"id":1,"word":"a","links":[[1]]}]}"#,
+            r#"{"order":1,"total_tokens_trained":0,"nodes":[{"id":0,"word":"a","links":[]}]}"#,
+            r#"{"order":1,"total_tokens_trained":0,"nodes":[]} x"#,
+            r#"{"order":1,"total_tokens_trained":0,"nodes":["\q"]}"#,
+            r#"{"order":1,"total_tokens_trained":0,"nodes":[{"id":1,"word":"\ud800\u0041","links":[]}]}"#,
+            r#"{"order":1,"total_tokens_trained":0,"nodes":[{"id":1,"word":"\u+123","links":[]}]}"#,
+            "[".repeat(200000).as_str(),
+            format!(
+                "{}{}",
+                "[".repeat(MAX_JSON_DEPTH + 1),
+                "]".repeat(MAX_JSON_DEPTH + 1)
+            )
+            .as_str(),
+        ] {
+            assert!(
+                matches!(MarkovChain::from_json(json), Err(MarkovError::Parse(_))),
+                "{json}"
+            );
+        }
+        let nested = format!(
+            "{}{}",
+            "[".repeat(MAX_JSON_DEPTH),
+            "]".repeat(MAX_JSON_DEPTH)
+        );
+        assert!(parse_json(&nested).is_ok());
+        assert!(matches!(
+            MarkovChain::from_json(r#"{"order":0,"total_tokens_trained":0,"nodes":[]}"#),
+            Err(MarkovError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn transition_pairs_are_sorted() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("b a c a c".to_string());
+        let pairs = chain.to_transition_pairs();
+        let expected = [
+            ("", "b", 1.0),
+            ("a", "c", 1.0),
+            ("b", "a", 1.0),
+            ("c", "", 0.5),
+            ("c", "a", 0.5),
+        ];
+        assert_eq!(pairs.len(), expected.len());
+        for ((from, to, probability), expected) in pairs.iter().zip(expected) {
+            assert_eq!((from.as_str(), to.as_str(), *probability), expected);
+        }
+    }
+
+    #[test]
+    fn mermaid_output_is_valid() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("the cat saw the \"dog\". the end".to_string());
+        chain.train_word("a<b>", "c#d");
+        let diagram = chain.to_mermaid(None);
+        let mut lines = diagram.lines();
+        assert_eq!(lines.next(), Some("graph LR"));
+        let node = Regex::new(r#"^    n\d+\["[^"<>]*"\]$"#).unwrap();
+        let edge = Regex::new(r"^    n\d+ -->\|[01]\.\d\d\| n\d+$").unwrap();
+        let mut edges = 0;
+        for line in lines {
+            if edge.is_match(line) {
+                edges += 1;
+            } else {
+                assert!(node.is_match(line), "{line:?}");
+            }
+        }
+        assert_eq!(edges, 9);
+        assert!(diagram.contains("-->|0.33|"));
+
+        let limited = chain.to_mermaid(Some(2));
+        assert_eq!(
+            limited.lines().filter(|line| edge.is_match(line)).count(),
+            2
+        );
+    }
+}
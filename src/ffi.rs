@@ -0,0 +1,166 @@
+#![allow(unsafe_code)]
+
+use std::ffi::{c_char, c_int, CStr};
+
+use crate::error::MarkovError;
+use crate::types::MarkovChain;
+
+#[doc = "Returned when a pointer that must not be null is null, or the buffer"]
+#[doc = "has no room at all."]
+pub const MARKOV_CHAIN_ERR_NULL: c_int = -1;
+#[doc = "Returned by [markov_chain_generate] when nothing can be generated"]
+#[doc = "because the chain hasn't been trained."]
+pub const MARKOV_CHAIN_ERR_EMPTY: c_int = -2;
+#[doc = "Returned by [markov_chain_generate] when the text and its NUL don't"]
+#[doc = "fit in the buffer."]
+pub const MARKOV_CHAIN_ERR_BUFFER_TOO_SMALL: c_int = -3;
+#[doc = "Returned by [markov_chain_generate] when generating fails for any"]
+#[doc = "other reason."]
+pub const MARKOV_CHAIN_ERR_GENERATION: c_int = -4;
+
+#[doc = "The markov_chain_create function creates an empty [MarkovChain] and"]
+#[doc = "returns a pointer to it. The caller owns the chain and must give it"]
+#[doc = "back to [markov_chain_free] exactly once; it must not be freed any"]
+#[doc = "other way."]
+#[no_mangle]
+pub extern "C" fn markov_chain_create() -> *mut MarkovChain {
+    Box::into_raw(Box::new(MarkovChain::new()))
+}
+
+#[doc = "The markov_chain_train_text function trains the chain on the"]
+#[doc = "NUL-terminated text, like [MarkovChain::train_text]. Invalid UTF-8"]
+#[doc = "is replaced with U+FFFD. The text is only read during the call and"]
+#[doc = "still belongs to the caller afterwards. Returns 0, or"]
+#[doc = "[MARKOV_CHAIN_ERR_NULL] if either pointer is null."]
+#[doc = ""]
+#[doc = "# Safety"]
+#[doc = ""]
+#[doc = "chain must be null or come from [markov_chain_create] and not have"]
+#[doc = "been freed, with no other call using it at the same time. text must"]
+#[doc = "be null or point to a NUL-terminated string."]
+#[no_mangle]
+pub unsafe extern "C" fn markov_chain_train_text(
+    chain: *mut MarkovChain,
+    text: *const c_char,
+) -> c_int {
+    if text.is_null() {
+        return MARKOV_CHAIN_ERR_NULL;
+    }
+    let chain = match chain.as_mut() {
+        Some(chain) => chain,
+        None => return MARKOV_CHAIN_ERR_NULL,
+    };
+    let text = CStr::from_ptr(text).to_string_lossy().into_owned();
+    chain.train_text(text);
+    0
+}
+
+#[doc = "The markov_chain_generate function generates up to max_words words,"]
+#[doc = "like [MarkovChain::generate_text], and writes them to buf as a"]
+#[doc = "NUL-terminated string. buf belongs to the caller and buf_len is its"]
+#[doc = "size in bytes, NUL included. Returns the number of bytes written"]
+#[doc = "without the NUL, or [MARKOV_CHAIN_ERR_NULL], [MARKOV_CHAIN_ERR_EMPTY],"]
+#[doc = "[MARKOV_CHAIN_ERR_BUFFER_TOO_SMALL] or [MARKOV_CHAIN_ERR_GENERATION]."]
+#[doc = "On an error buf is left as an empty string if it has room for one."]
+#[doc = ""]
+#[doc = "# Safety"]
+#[doc = ""]
+#[doc = "chain must be null or come from [markov_chain_create] and not have"]
+#[doc = "been freed, with no call training it at the same time. buf must be"]
+#[doc = "null or valid for writing buf_len bytes."]
+#[no_mangle]
+pub unsafe extern "C" fn markov_chain_generate(
+    chain: *const MarkovChain,
+    max_words: usize,
+    buf: *mut c_char,
+    buf_len: usize,
+) -> c_int {
+    if buf.is_null() || buf_len == 0 {
+        return MARKOV_CHAIN_ERR_NULL;
+    }
+    let out = std::slice::from_raw_parts_mut(buf.cast::<u8>(), buf_len);
+    out[0] = 0;
+    let chain = match chain.as_ref() {
+        Some(chain) => chain,
+        None => return MARKOV_CHAIN_ERR_NULL,
+    };
+    let text = match chain.generate_text(max_words) {
+        Ok(text) => text,
+        Err(MarkovError::EmptyChain) => return MARKOV_CHAIN_ERR_EMPTY,
+        Err(_) => return MARKOV_CHAIN_ERR_GENERATION,
+    };
+    let written = match c_int::try_from(text.len()) {
+        Ok(written) if text.len() < buf_len => written,
+        _ => return MARKOV_CHAIN_ERR_BUFFER_TOO_SMALL,
+    };
+    out[..text.len()].copy_from_slice(text.as_bytes());
+    out[text.len()] = 0;
+    written
+}
+
+#[doc = "The markov_chain_free function frees a chain made with"]
+#[doc = "[markov_chain_create]. Null does nothing."]
+#[doc = ""]
+#[doc = "# Safety"]
+#[doc = ""]
+#[doc = "chain must be null or come from [markov_chain_create] and not have"]
+#[doc = "been freed already. It can't be used again afterwards."]
+#[no_mangle]
+pub unsafe extern "C" fn markov_chain_free(chain: *mut MarkovChain) {
+    if !chain.is_null() {
+        drop(Box::from_raw(chain));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+    use std::ptr;
+
+    #[test]
+    fn chains_can_be_used_through_the_c_abi() {
+        let chain = markov_chain_create();
+        let mut buf = [1 as c_char; 32];
+        unsafe {
+            assert_eq!(
+                markov_chain_generate(chain, 10, buf.as_mut_ptr(), buf.len()),
+                MARKOV_CHAIN_ERR_EMPTY
+            );
+            assert_eq!(buf[0], 0);
+
+            let text = CString::new("the cat sat").unwrap();
+            assert_eq!(markov_chain_train_text(chain, text.as_ptr()), 0);
+            let written = markov_chain_generate(chain, 10, buf.as_mut_ptr(), buf.len());
+            assert_eq!(written, 11);
+            let out = CStr::from_ptr(buf.as_ptr());
+            assert_eq!(out.to_str().unwrap(), "the cat sat");
+
+            // "the cat sat" and its NUL need 12 bytes
+            assert_eq!(
+                markov_chain_generate(chain, 10, buf.as_mut_ptr(), 11),
+                MARKOV_CHAIN_ERR_BUFFER_TOO_SMALL
+            );
+            assert_eq!(markov_chain_generate(chain, 10, buf.as_mut_ptr(), 12), 11);
+
+            assert_eq!(
+                markov_chain_train_text(ptr::null_mut(), text.as_ptr()),
+                MARKOV_CHAIN_ERR_NULL
+            );
+            assert_eq!(
+                markov_chain_train_text(chain, ptr::null()),
+                MARKOV_CHAIN_ERR_NULL
+            );
+            assert_eq!(
+                markov_chain_generate(chain, 10, ptr::null_mut(), 32),
+                MARKOV_CHAIN_ERR_NULL
+            );
+            assert_eq!(
+                markov_chain_generate(ptr::null(), 10, buf.as_mut_ptr(), buf.len()),
+                MARKOV_CHAIN_ERR_NULL
+            );
+            markov_chain_free(chain);
+            markov_chain_free(ptr::null_mut());
+        }
+    }
+}
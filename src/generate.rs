@@ -0,0 +1,1608 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use crate::error::MarkovError;
+use crate::tokenizer::{is_punctuation, join_words};
+use crate::types::{default_rng, sample_weighted, Map, MarkovChain, MarkovNode, MarkovNodeID};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+
+#[doc = "The START_TOKEN constant is the word that marks the start of a"]
+#[doc = "sentence in words trained with markers, it is left out of"]
+#[doc = "[MarkovChain::generate_sentence]'s output."]
+pub const START_TOKEN: &str = "<START>";
+#[doc = "The END_TOKEN constant is the word that marks the end of a sentence in"]
+#[doc = "words trained with markers, [MarkovChain::generate_sentence] stops"]
+#[doc = "there and leaves it out."]
+pub const END_TOKEN: &str = "<END>";
+#[doc = "The DEFAULT_MAX_SENTENCE_WORDS constant is how many words"]
+#[doc = "[MarkovChain::generate_sentence] generates at most, in case the chain"]
+#[doc = "loops without ever reaching the end of a sentence."]
+pub const DEFAULT_MAX_SENTENCE_WORDS: usize = 200;
+#[doc = "The MAX_GENERATION_ATTEMPTS constant is how many times generation is"]
+#[doc = "tried before giving up, like [MarkovChain::generate_sentence] does"]
+#[doc = "with a novelty check and [MarkovChain::generate_with] does by default"]
+#[doc = "when it can't reach [GenerationOptions::min_words]."]
+pub const MAX_GENERATION_ATTEMPTS: usize = 100;
+#[doc = "The DEFAULT_MAX_RETRIES constant is the default"]
+#[doc = "[GenerationOptions::max_retries], so [MAX_GENERATION_ATTEMPTS] walks"]
+#[doc = "are made in all."]
+pub const DEFAULT_MAX_RETRIES: usize = MAX_GENERATION_ATTEMPTS - 1;
+#[doc = "The DEFAULT_MAX_VISITS constant is how many times"]
+#[doc = "[MarkovChain::generate_with] visits the same node by default before"]
+#[doc = "picking other links, so loops like \"a b a b\" can't go on forever."]
+pub const DEFAULT_MAX_VISITS: usize = 10;
+
+#[doc = "The GenerationOptions struct configures [MarkovChain::generate_with]."]
+#[doc = "The default generates a sentence from the root like"]
+#[doc = "[MarkovChain::generate_sentence] does, without any restrictions and"]
+#[doc = "without formatting it as a sentence."]
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenerationOptions {
+    pub max_words: usize, // most words to generate, defaults to DEFAULT_MAX_SENTENCE_WORDS
+    pub min_words: usize, // fewest words to generate, shorter texts are generated again
+    pub max_retries: usize, // most times a text shorter than min_words is generated again, defaults to DEFAULT_MAX_RETRIES
+    pub start_word: Option<String>, // word (or words, for higher-order chains) to start from instead of the root
+    pub stop_at_terminal: bool, // if false, the end of a sentence is never picked, only max_words or running out of links stops
+    pub seed: Option<u64>,      // seed for the rng, for the same text every time
+    pub banned_words: Vec<String>, // words that are never generated (the start word still is)
+    pub allow_immediate_repeat: bool, // if false, a word is never followed by itself, the walk ends if nothing else is left
+    pub temperature: f64, // 1 samples by portions, 0 always picks the most portions, higher is closer to uniform
+    pub max_visits: Option<usize>, // most times a node is visited in one walk, links to it are then ignored
+    pub format_sentence: bool, // if true, the text is capitalized and punctuated like generate_sentence does
+}
+
+impl Default for GenerationOptions {
+    fn default() -> GenerationOptions {
+        GenerationOptions {
+            max_words: DEFAULT_MAX_SENTENCE_WORDS,
+            min_words: 0,
+            max_retries: DEFAULT_MAX_RETRIES,
+            start_word: None,
+            stop_at_terminal: true,
+            seed: None,
+            banned_words: Vec::new(),
+            allow_immediate_repeat: true,
+            temperature: 1.0,
+            max_visits: Some(DEFAULT_MAX_VISITS),
+            format_sentence: false,
+        }
+    }
+}
+
+#[doc = "The GenerationOutcome struct is what"]
+#[doc = "[MarkovChain::generate_with_outcome] generated, and if it reached"]
+#[doc = "[GenerationOptions::min_words]."]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GenerationOutcome {
+    pub text: String, // the generated text, the longest attempt if none was long enough
+    pub words: usize, // how many words the text has
+    pub attempts: usize, // how many times the text was generated, 1 if the first was long enough
+    pub satisfied: bool, // if the text has at least min_words words
+}
+
+#[doc = "The nucleus function returns the smallest set of the most likely links"]
+#[doc = "whose probabilities add up to at least p, most likely first. Links"]
+#[doc = "with the same portions are kept in id order so the result is stable."]
+fn nucleus(links: &Map<MarkovNodeID, u64>, p: f64) -> Vec<(MarkovNodeID, u64)> {
+    let mut sorted: Vec<(MarkovNodeID, u64)> = links
+        .iter()
+        .map(|(id, portions)| (*id, *portions))
+        .collect();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let total: u64 = sorted.iter().map(|(_, portions)| portions).sum();
+    let mut sum = 0;
+    let mut kept = 0;
+    for (_, portions) in &sorted {
+        sum += portions;
+        kept += 1;
+        if sum as f64 >= p * total as f64 {
+            break;
+        }
+    }
+    sorted.truncate(kept);
+    sorted
+}
+
+#[doc = "The temperature_weights function returns the weight of each choice"]
+#[doc = "after applying the temperature: each probability is raised to the"]
+#[doc = "power of 1 / temperature, so 1 keeps the portions' distribution and"]
+#[doc = "higher temperatures flatten it. The probabilities are divided by the"]
+#[doc = "largest one first, so the most likely choice always weighs 1 and the"]
+#[doc = "weights can't all underflow to 0 or overflow. A temperature of 0 gives"]
+#[doc = "the first most likely choice all of the weight."]
+fn temperature_weights(
+    choices: &[(MarkovNodeID, u64)],
+    temperature: f64,
+) -> Vec<(MarkovNodeID, f64)> {
+    let most = choices
+        .iter()
+        .map(|(_, portions)| *portions)
+        .max()
+        .unwrap_or(0);
+    if temperature == 0.0 {
+        let greedy = choices.iter().position(|(_, portions)| *portions == most);
+        return choices
+            .iter()
+            .enumerate()
+            .map(|(i, (id, _))| (*id, if Some(i) == greedy { 1.0 } else { 0.0 }))
+            .collect();
+    }
+    choices
+        .iter()
+        .map(|(id, portions)| {
+            (
+                *id,
+                (*portions as f64 / most as f64).powf(1.0 / temperature),
+            )
+        })
+        .collect()
+}
+
+#[doc = "The sample_with_temperature function picks one of the choices at"]
+#[doc = "random, weighted by [temperature_weights]. A temperature of 1 samples"]
+#[doc = "exactly like [sample_weighted] and 0 doesn't use the rng at all."]
+fn sample_with_temperature<I, R>(choices: I, temperature: f64, rng: &mut R) -> Option<MarkovNodeID>
+where
+    I: Iterator<Item = (MarkovNodeID, u64)> + Clone,
+    R: Rng + ?Sized,
+{
+    if temperature == 1.0 {
+        return sample_weighted(choices, rng);
+    }
+    let choices: Vec<(MarkovNodeID, u64)> = choices.filter(|(_, portions)| *portions > 0).collect();
+    let weights = temperature_weights(&choices, temperature);
+    if temperature == 0.0 {
+        return weights
+            .iter()
+            .find(|(_, weight)| *weight > 0.0)
+            .map(|(id, _)| *id);
+    }
+    let total: f64 = weights.iter().map(|(_, weight)| weight).sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let mut pick = rng.gen::<f64>() * total;
+    for (id, weight) in &weights {
+        if pick < *weight {
+            return Some(*id);
+        }
+        pick -= weight;
+    }
+    weights.last().map(|(id, _)| *id)
+}
+
+#[doc = "The Walk struct is an iterator over the words of a random walk"]
+#[doc = "through a [MarkovChain], made with [MarkovChain::walk]. Each word is"]
+#[doc = "only picked when it is asked for, and the walk ends at the terminal or"]
+#[doc = "a word with no links, so it can be cut short with [Iterator::take]."]
+pub struct Walk<'a> {
+    chain: &'a MarkovChain,
+    current: &'a MarkovNode,
+    pending: VecDeque<String>, // words of the current node not returned yet
+    rng: Box<dyn RngCore + 'a>,
+    done: bool,
+}
+
+impl Iterator for Walk<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if let Some(word) = self.pending.pop_front() {
+            return Some(word);
+        }
+        if self.done {
+            return None;
+        }
+        let links = self.current.links.iter().map(|(id, p)| (*id, *p));
+        let next = match sample_weighted(links, &mut self.rng) {
+            Some(next) if next != self.chain.root.id => self.chain.node(next)?,
+            _ => {
+                self.done = true;
+                return None;
+            }
+        };
+        if self.current.id == self.chain.root.id {
+            self.pending.extend(self.chain.surface_words(next));
+        } else if let Some(word) = next.data.rsplit(' ').next() {
+            self.pending
+                .push_back(self.chain.surface_form(word).to_string());
+        }
+        self.current = next;
+        self.pending.pop_front()
+    }
+}
+
+impl MarkovChain {
+    #[doc = "The walk function returns a [Walk] from the root, which picks each"]
+    #[doc = "word like [MarkovChain::generate] does but only when it is asked"]
+    #[doc = "for the next one, for handing words out one at a time. An untrained"]
+    #[doc = "chain gives an empty walk."]
+    pub fn walk(&self) -> Walk<'_> {
+        self.walk_with_rng(default_rng())
+    }
+    #[doc = "The walk_with_rng function is the same as [MarkovChain::walk] but"]
+    #[doc = "samples with the provided rng."]
+    pub fn walk_with_rng<'a>(&'a self, rng: impl RngCore + 'a) -> Walk<'a> {
+        Walk {
+            chain: self,
+            current: &self.root,
+            pending: VecDeque::new(),
+            rng: Box::new(rng),
+            done: false,
+        }
+    }
+    #[doc = "The walk_from function returns a [Walk] that starts with the"]
+    #[doc = "provided word (or words, for higher-order chains) and continues from"]
+    #[doc = "it. An unknown word gives [MarkovError::WordNotFound]."]
+    pub fn walk_from(&self, word: &str) -> Result<Walk<'_>, MarkovError> {
+        let start = self
+            .find_node(word)
+            .ok_or_else(|| MarkovError::WordNotFound(word.to_string()))?;
+        let mut walk = self.walk();
+        walk.current = start;
+        walk.pending.extend(self.surface_words(start));
+        Ok(walk)
+    }
+    #[doc = "The generate_until function walks from the root like"]
+    #[doc = "[MarkovChain::walk], calling pred with the words so far after each"]
+    #[doc = "word is added, and stops as soon as it returns true. The words are"]
+    #[doc = "returned including the one that made pred return true. The walk also"]
+    #[doc = "stops at the terminal, a word with no links or after max_words"]
+    #[doc = "words, whichever comes first. An untrained chain gives"]
+    #[doc = "[MarkovError::EmptyChain]."]
+    pub fn generate_until<P>(
+        &self,
+        mut pred: P,
+        rng: &mut impl Rng,
+        max_words: usize,
+    ) -> Result<Vec<String>, MarkovError>
+    where
+        P: FnMut(&[String]) -> bool,
+    {
+        if self.root.links.is_empty() {
+            return Err(MarkovError::EmptyChain);
+        }
+        let mut out = Vec::new();
+        for word in self.walk_with_rng(rng).take(max_words) {
+            out.push(word);
+            if pred(&out) {
+                break;
+            }
+        }
+        Ok(out)
+    }
+    #[doc = "The generate_nucleus function generates up to max_words words with"]
+    #[doc = "nucleus (top-p) sampling: at each step only the most likely"]
+    #[doc = "successors covering at least p of the probability are kept, and one"]
+    #[doc = "of them is picked in proportion to its portions. A seed starts the"]
+    #[doc = "text from that word (its words count towards max_words), otherwise"]
+    #[doc = "it starts from the root. p must be in (0, 1]."]
+    pub fn generate_nucleus(
+        &self,
+        p: f64,
+        seed: Option<&str>,
+        max_words: usize,
+        rng: &mut impl Rng,
+    ) -> Result<Vec<String>, MarkovError> {
+        if !(p > 0.0 && p <= 1.0) {
+            return Err(MarkovError::InvalidConfig(format!(
+                "nucleus p must be in (0, 1], got {p}"
+            )));
+        }
+        let mut out = Vec::new();
+        let start = match seed {
+            Some(seed) => {
+                let node = self
+                    .find_node(seed)
+                    .ok_or_else(|| MarkovError::WordNotFound(seed.to_string()))?;
+                out.extend(self.surface_words(node).take(max_words));
+                node
+            }
+            None => {
+                if self.root.links.is_empty() {
+                    return Err(MarkovError::EmptyChain);
+                }
+                &self.root
+            }
+        };
+        let remaining = max_words - out.len();
+        self.walk_into(start, remaining, &mut out, |node| {
+            sample_weighted(nucleus(&node.links, p).into_iter(), rng)
+        });
+        Ok(out)
+    }
+    #[doc = "The generate_sentence function generates a single sentence from the"]
+    #[doc = "root, stopping when it reaches the terminal or an [END_TOKEN] word"]
+    #[doc = "(for words trained with [MarkovChain::train_words] using markers),"]
+    #[doc = "and returns it joined with [join_words], with its first letter and"]
+    #[doc = "every \"i\" capitalized and a period added unless it ends with"]
+    #[doc = "punctuation (case-sensitive chains only get the period, their words"]
+    #[doc = "are kept as trained). [MarkovChain::generate_with] gives the words"]
+    #[doc = "without this unless [GenerationOptions::format_sentence] is set."]
+    #[doc = "[START_TOKEN] and [END_TOKEN] are left out, and at most"]
+    #[doc = "[DEFAULT_MAX_SENTENCE_WORDS] words are generated in case the"]
+    #[doc = "sentence never ends (like in chains trained without a terminal)."]
+    #[doc = "An untrained chain gives"]
+    #[doc = "[MarkovError::EmptyChain] instead of an empty sentence. It samples"]
+    #[doc = "like [MarkovChain::generate], see"]
+    #[doc = "[MarkovChain::generate_sentence_with_rng] for reproducible output."]
+    #[doc = "Chains built with [crate::types::MarkovChainBuilder::novelty_check]"]
+    #[doc = "throw away sentences that repeat the training text and try again, up"]
+    #[doc = "to [MAX_GENERATION_ATTEMPTS] times before giving up with"]
+    #[doc = "[MarkovError::GenerationFailed]."]
+    pub fn generate_sentence(&self) -> Result<String, MarkovError> {
+        self.generate_sentence_with_rng(&mut default_rng())
+    }
+    #[doc = "The generate_sentence_with_rng function is the same as"]
+    #[doc = "[MarkovChain::generate_sentence] but does all of its sampling with"]
+    #[doc = "the provided rng."]
+    pub fn generate_sentence_with_rng(&self, rng: &mut impl Rng) -> Result<String, MarkovError> {
+        if self.root.links.is_empty() {
+            return Err(MarkovError::EmptyChain);
+        }
+        let novelty = match &self.novelty {
+            Some(novelty) => novelty,
+            None => return Ok(self.format_sentence(&self.walk_sentence(rng))),
+        };
+        for _ in 0..MAX_GENERATION_ATTEMPTS {
+            let out = self.walk_sentence(rng);
+            let words: Vec<String> = out
+                .iter()
+                .map(|word| self.normalize(word).into_owned())
+                .collect();
+            if novelty.is_novel(&words) {
+                return Ok(self.format_sentence(&out));
+            }
+        }
+        Err(MarkovError::GenerationFailed {
+            attempts: MAX_GENERATION_ATTEMPTS,
+        })
+    }
+    #[doc = "The walk_sentence function is a single walk of"]
+    #[doc = "[MarkovChain::generate_sentence_with_rng], returning its words"]
+    #[doc = "without [START_TOKEN] and [END_TOKEN]."]
+    fn walk_sentence(&self, rng: &mut impl Rng) -> Vec<String> {
+        let mut out = Vec::new();
+        self.walk_into(&self.root, DEFAULT_MAX_SENTENCE_WORDS, &mut out, |node| {
+            if node.data.rsplit(' ').next() == Some(END_TOKEN) {
+                return None;
+            }
+            sample_weighted(node.links.iter().map(|(id, p)| (*id, *p)), rng)
+        });
+        out.retain(|word| word != START_TOKEN && word != END_TOKEN);
+        out
+    }
+    #[doc = "The format_sentence function joins the words with [join_words] and"]
+    #[doc = "makes them look like a sentence: the first letter and every \"i\" are"]
+    #[doc = "capitalized, since training lowercases everything, and a period is"]
+    #[doc = "added unless it already ends with punctuation. Case-sensitive chains"]
+    #[doc = "already have the words as they were trained, so only the period is"]
+    #[doc = "added. No words give an empty string."]
+    pub(crate) fn format_sentence(&self, words: &[String]) -> String {
+        let words: Vec<String> = words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| match self.case_sensitive() {
+                true => word.clone(),
+                false if word == "i" => "I".to_string(),
+                false if i == 0 => {
+                    let mut chars = word.chars();
+                    match chars.next() {
+                        Some(first) => first.to_uppercase().chain(chars).collect(),
+                        None => String::new(),
+                    }
+                }
+                false => word.clone(),
+            })
+            .collect();
+        let mut out = join_words(&words);
+        if !out.is_empty()
+            && !out
+                .chars()
+                .last()
+                .is_some_and(|last| is_punctuation(&last.to_string()))
+        {
+            out.push('.');
+        }
+        out
+    }
+    #[doc = "The generate_sentences function generates n sentences with"]
+    #[doc = "[MarkovChain::generate_sentence], each a walk of its own from the"]
+    #[doc = "root. An untrained chain gives no sentences."]
+    pub fn generate_sentences(&self, n: usize) -> Vec<String> {
+        self.generate_sentences_with_rng(n, &mut default_rng())
+    }
+    #[doc = "The generate_sentences_with_rng function is the same as"]
+    #[doc = "[MarkovChain::generate_sentences] but samples with the provided rng,"]
+    #[doc = "one sentence after the other, so a seeded rng gives the same"]
+    #[doc = "sentences every time."]
+    pub fn generate_sentences_with_rng(&self, n: usize, rng: &mut impl Rng) -> Vec<String> {
+        (0..n)
+            .map_while(|_| self.generate_sentence_with_rng(rng).ok())
+            .collect()
+    }
+    #[doc = "The sentences function returns an endless iterator of sentences from"]
+    #[doc = "[MarkovChain::generate_sentence_with_rng], all sampled with one"]
+    #[doc = "StdRng seeded with seed, so the same seed always gives the same"]
+    #[doc = "sentences. Empty sentences are skipped; the iterator only ends if the"]
+    #[doc = "chain can't generate (it is untrained, or the novelty check gives up)"]
+    #[doc = "or gives [MAX_GENERATION_ATTEMPTS] empty sentences in a row."]
+    pub fn sentences(&self, seed: u64) -> impl Iterator<Item = String> + '_ {
+        let mut rng = StdRng::seed_from_u64(seed);
+        std::iter::from_fn(move || {
+            for _ in 0..MAX_GENERATION_ATTEMPTS {
+                let sentence = self.generate_sentence_with_rng(&mut rng).ok()?;
+                if !sentence.is_empty() {
+                    return Some(sentence);
+                }
+            }
+            None
+        })
+    }
+    #[doc = "The generate_paragraph function joins n sentences from"]
+    #[doc = "[MarkovChain::generate_sentences] into a paragraph, each already"]
+    #[doc = "capitalized and ending with punctuation."]
+    pub fn generate_paragraph(&self, n: usize) -> String {
+        self.generate_paragraph_with_rng(n, &mut default_rng())
+    }
+    #[doc = "The generate_paragraph_with_rng function is the same as"]
+    #[doc = "[MarkovChain::generate_paragraph] but samples with the provided rng."]
+    pub fn generate_paragraph_with_rng(&self, n: usize, rng: &mut impl Rng) -> String {
+        let sentences: Vec<String> = self
+            .generate_sentences_with_rng(n, rng)
+            .into_iter()
+            .filter(|sentence| !sentence.is_empty())
+            .collect();
+        sentences.join(" ")
+    }
+    #[doc = "The generate_with function generates text as configured by opts (see"]
+    #[doc = "[GenerationOptions]) and returns it joined with [join_words]. Walks"]
+    #[doc = "that end before min_words words are thrown away and generated again,"]
+    #[doc = "up to max_retries more times, after which it gives up with"]
+    #[doc = "[MarkovError::GenerationFailed] (see"]
+    #[doc = "[MarkovChain::generate_with_outcome] for the longest attempt"]
+    #[doc = "instead). It returns [MarkovError::InvalidConfig] if min_words is"]
+    #[doc = "more than max_words, the temperature is negative or NaN, or"]
+    #[doc = "max_visits is 0."]
+    #[doc = "An unknown start_word gives [MarkovError::WordNotFound]. However"]
+    #[doc = "the chain loops, no more than max_words words are generated, and"]
+    #[doc = "once a node has been visited max_visits times the walk picks among"]
+    #[doc = "the other links, ending if there are none."]
+    #[doc = ""]
+    #[doc = "Banned words are normalized like [MarkovChain::find_node] does, so"]
+    #[doc = "banning \"Word\" in a case-insensitive chain bans \"word\" too. Links"]
+    #[doc = "to them are left out when sampling, so the other links share their"]
+    #[doc = "probability, and a walk where every link left is to a banned word"]
+    #[doc = "ends there instead of backtracking (retrying if that is too short for"]
+    #[doc = "min_words)."]
+    pub fn generate_with(&self, opts: &GenerationOptions) -> Result<String, MarkovError> {
+        let outcome = self.generate_with_outcome(opts)?;
+        if !outcome.satisfied {
+            return Err(MarkovError::GenerationFailed {
+                attempts: outcome.attempts,
+            });
+        }
+        Ok(outcome.text)
+    }
+    #[doc = "The generate_with_outcome function is the same as"]
+    #[doc = "[MarkovChain::generate_with], but when no attempt reaches min_words"]
+    #[doc = "it returns the longest one (the first, if there is a tie) instead of"]
+    #[doc = "an error, and says so in the [GenerationOutcome]. Retrying only"]
+    #[doc = "walks the chain again, nothing in it is copied."]
+    pub fn generate_with_outcome(
+        &self,
+        opts: &GenerationOptions,
+    ) -> Result<GenerationOutcome, MarkovError> {
+        match opts.seed {
+            Some(seed) => self.generate_with_options(opts, &mut StdRng::seed_from_u64(seed)),
+            None => self.generate_with_options(opts, &mut default_rng()),
+        }
+    }
+    #[doc = "The generate_with_options function is"]
+    #[doc = "[MarkovChain::generate_with_outcome] once the rng has been picked."]
+    fn generate_with_options(
+        &self,
+        opts: &GenerationOptions,
+        rng: &mut impl Rng,
+    ) -> Result<GenerationOutcome, MarkovError> {
+        if opts.min_words > opts.max_words {
+            return Err(MarkovError::InvalidConfig(format!(
+                "min_words ({}) is more than max_words ({})",
+                opts.min_words, opts.max_words
+            )));
+        }
+        if opts.temperature.is_nan() || opts.temperature < 0.0 {
+            return Err(MarkovError::InvalidConfig(format!(
+                "temperature can't be negative, got {}",
+                opts.temperature
+            )));
+        }
+        if opts.max_visits == Some(0) {
+            return Err(MarkovError::InvalidConfig(
+                "max_visits must be at least 1".to_string(),
+            ));
+        }
+        let start = match &opts.start_word {
+            Some(word) => self
+                .find_node(word)
+                .ok_or_else(|| MarkovError::WordNotFound(word.to_string()))?,
+            None if self.root.links.is_empty() => return Err(MarkovError::EmptyChain),
+            None => &self.root,
+        };
+        let banned: HashSet<String> = opts
+            .banned_words
+            .iter()
+            .map(|word| self.normalize(word).into_owned())
+            .collect();
+        let last_word = |node: &MarkovNode| node.data.rsplit(' ').next().map(str::to_owned);
+        let allowed = |from: &MarkovNode, to: MarkovNodeID| {
+            if to == self.root.id {
+                return opts.stop_at_terminal;
+            }
+            let to = match self.node(to).and_then(last_word) {
+                Some(to) => to,
+                None => return false,
+            };
+            !banned.contains(&to)
+                && (opts.allow_immediate_repeat
+                    || from.id == self.root.id
+                    || last_word(from) != Some(to))
+        };
+        let prefix: Vec<String> = match &opts.start_word {
+            Some(_) => self.surface_words(start).take(opts.max_words).collect(),
+            None => Vec::new(),
+        };
+        let remaining = opts.max_words - prefix.len();
+        let mut out = Vec::new();
+        let mut longest: Vec<String> = Vec::new();
+        let mut visits: HashMap<MarkovNodeID, usize> = HashMap::new();
+        let mut attempts = 0;
+        while attempts <= opts.max_retries {
+            attempts += 1;
+            out.clone_from(&prefix);
+            visits.clear();
+            self.walk_into(start, remaining, &mut out, |node| {
+                *visits.entry(node.id).or_insert(0) += 1;
+                let visited = |to: MarkovNodeID| match opts.max_visits {
+                    Some(max) => visits.get(&to).is_some_and(|visits| *visits >= max),
+                    None => false,
+                };
+                let links = node
+                    .links
+                    .iter()
+                    .filter(|(to, _)| allowed(node, **to) && !visited(**to));
+                sample_with_temperature(links.map(|(id, p)| (*id, *p)), opts.temperature, rng)
+            });
+            if out.len() > longest.len() {
+                std::mem::swap(&mut out, &mut longest);
+            }
+            if longest.len() >= opts.min_words {
+                break;
+            }
+        }
+        Ok(GenerationOutcome {
+            text: match opts.format_sentence {
+                true => self.format_sentence(&longest),
+                false => join_words(&longest),
+            },
+            words: longest.len(),
+            attempts,
+            satisfied: longest.len() >= opts.min_words,
+        })
+    }
+    #[doc = "The most_likely_path function returns the sequence of words after"]
+    #[doc = "seed (normalized like [MarkovChain::find_node]) with the highest"]
+    #[doc = "probability of being generated, found with the Viterbi algorithm"]
+    #[doc = "instead of greedily picking the likeliest link at every step. The"]
+    #[doc = "result is seed followed by length more words, the end of a sentence"]
+    #[doc = "is never picked, and if no path is that long the likeliest of the"]
+    #[doc = "longest paths is returned. Ties go to the node with the lowest id. It"]
+    #[doc = "takes O(length * links) time and O(length * nodes) space."]
+    pub fn most_likely_path(&self, seed: &str, length: usize) -> Result<Vec<String>, MarkovError> {
+        let start = self
+            .find_node(seed)
+            .ok_or_else(|| MarkovError::WordNotFound(seed.to_string()))?;
+        // layers[step] maps each node reachable in step links to the log
+        // probability of the likeliest path there and the node before it
+        let mut layers: Vec<BTreeMap<MarkovNodeID, (f64, MarkovNodeID)>> =
+            vec![BTreeMap::from([(start.id, (0.0, start.id))])];
+        for _ in 0..length {
+            let mut next: BTreeMap<MarkovNodeID, (f64, MarkovNodeID)> = BTreeMap::new();
+            for (id, (log_probability, _)) in &layers[layers.len() - 1] {
+                let node = match self.node(*id) {
+                    Some(node) => node,
+                    None => continue,
+                };
+                let total: u64 = node.links.iter().map(|(_, portions)| *portions).sum();
+                for (to, portions) in node.links.iter() {
+                    if *to == self.root.id || *portions == 0 {
+                        continue;
+                    }
+                    let candidate = log_probability + (*portions as f64 / total as f64).ln();
+                    let best = next.entry(*to).or_insert((f64::NEG_INFINITY, *id));
+                    if candidate > best.0 {
+                        *best = (candidate, *id);
+                    }
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            layers.push(next);
+        }
+        let mut id = layers[layers.len() - 1]
+            .iter()
+            .fold(
+                None,
+                |best: Option<(MarkovNodeID, f64)>, (id, (log_probability, _))| match best {
+                    Some((_, most)) if most >= *log_probability => best,
+                    _ => Some((*id, *log_probability)),
+                },
+            )
+            .map(|(id, _)| id)
+            .unwrap_or(start.id);
+        let mut path = Vec::with_capacity(layers.len());
+        for layer in layers.iter().skip(1).rev() {
+            path.push(id);
+            id = layer[&id].1;
+        }
+        let mut out: Vec<String> = self.surface_words(start).collect();
+        for id in path.into_iter().rev() {
+            if let Some(word) = self.node(id).and_then(|node| node.data.rsplit(' ').next()) {
+                out.push(self.surface_form(word).to_string());
+            }
+        }
+        Ok(out)
+    }
+    #[doc = "The generate_beam function generates the likeliest text it can find"]
+    #[doc = "with beam search: it keeps the beam_width likeliest partial texts"]
+    #[doc = "(by the sum of their links' log probabilities), extends each by"]
+    #[doc = "every link out of its last node, and stops once every text in the"]
+    #[doc = "beam has ended (at the terminal or a word with no links) or has"]
+    #[doc = "max_length words. It returns the likeliest text that ended, or the"]
+    #[doc = "likeliest unfinished one if none did. A seed starts the text from"]
+    #[doc = "that word (its words count towards max_length), otherwise it starts"]
+    #[doc = "from the root. A beam_width of 1 is greedy decoding."]
+    pub fn generate_beam(
+        &self,
+        beam_width: usize,
+        max_length: usize,
+        seed: Option<&str>,
+    ) -> Result<Vec<String>, MarkovError> {
+        if beam_width == 0 {
+            return Err(MarkovError::InvalidConfig(
+                "beam_width must be at least 1".to_string(),
+            ));
+        }
+        let start = match seed {
+            Some(seed) => self
+                .find_node(seed)
+                .ok_or_else(|| MarkovError::WordNotFound(seed.to_string()))?,
+            None if self.root.links.is_empty() => return Err(MarkovError::EmptyChain),
+            None => &self.root,
+        };
+        let mut first: Vec<String> = Vec::new();
+        if seed.is_some() {
+            first.extend(start.data.split(' ').take(max_length).map(String::from));
+        }
+        // each hypothesis is (words, log probability, last node, ended)
+        let mut beam = vec![(first, 0.0, start, false)];
+        while beam
+            .iter()
+            .any(|(words, _, _, ended)| !ended && words.len() < max_length)
+        {
+            let mut candidates = Vec::new();
+            for (words, log_probability, node, ended) in beam {
+                if ended || words.len() >= max_length {
+                    candidates.push((words, log_probability, node, ended));
+                    continue;
+                }
+                let total: u64 = node.links.iter().map(|(_, portions)| *portions).sum();
+                if total == 0 {
+                    candidates.push((words, log_probability, node, true));
+                    continue;
+                }
+                for (to, portions) in node.links.iter() {
+                    let log_probability = log_probability + (*portions as f64 / total as f64).ln();
+                    if *to == self.root.id {
+                        candidates.push((words.clone(), log_probability, node, true));
+                        continue;
+                    }
+                    let next = match self.node(*to) {
+                        Some(next) => next,
+                        None => continue,
+                    };
+                    let mut words = words.clone();
+                    if node.id == self.root.id {
+                        words.extend(next.data.split(' ').map(String::from));
+                        words.truncate(max_length);
+                    } else if let Some(word) = next.data.rsplit(' ').next() {
+                        words.push(word.to_string());
+                    }
+                    candidates.push((words, log_probability, next, false));
+                }
+            }
+            candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+            candidates.truncate(beam_width);
+            beam = candidates;
+        }
+        let best = beam
+            .iter()
+            .filter(|(_, _, _, ended)| *ended)
+            .chain(beam.iter())
+            .next()
+            .map(|(words, ..)| words.clone())
+            .unwrap_or_default();
+        Ok(best
+            .iter()
+            .map(|word| self.surface_form(word).to_string())
+            .collect())
+    }
+    #[doc = "The generate_n function generates count texts of up to max_words"]
+    #[doc = "words each with [MarkovChain::generate_with_rng], returning every"]
+    #[doc = "result, errors included, in the order they were generated."]
+    pub fn generate_n(
+        &self,
+        count: usize,
+        max_words: usize,
+        rng: &mut impl Rng,
+    ) -> Vec<Result<Vec<String>, MarkovError>> {
+        let out: Vec<_> = (0..count)
+            .map(|_| self.generate_with_rng(rng, max_words))
+            .collect();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            count,
+            failed = out.iter().filter(|result| result.is_err()).count(),
+            "generated texts"
+        );
+        out
+    }
+    #[doc = "The generate_n_unique function generates texts until it has count"]
+    #[doc = "different ones or has tried max_attempts times, so it can return"]
+    #[doc = "fewer than count (a small chain may not be able to make that many)."]
+    #[doc = "Texts are returned in the order they were first generated."]
+    pub fn generate_n_unique(
+        &self,
+        count: usize,
+        max_words: usize,
+        rng: &mut impl Rng,
+        max_attempts: usize,
+    ) -> Vec<Vec<String>> {
+        let mut seen = HashSet::new();
+        let mut out = Vec::new();
+        let mut attempts = 0;
+        while out.len() < count && attempts < max_attempts {
+            attempts += 1;
+            match self.generate_with_rng(rng, max_words) {
+                Ok(words) => {
+                    if seen.insert(words.clone()) {
+                        out.push(words);
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(count, found = out.len(), attempts, "generated unique texts");
+        out
+    }
+
+    #[doc = "The backoff_generate function generates like"]
+    #[doc = "[MarkovChain::complete_sentence], continuing seed_context, but"]
+    #[doc = "doesn't give up when the last words were never trained together: it"]
+    #[doc = "backs off to the last order - 1 words (using counts kept from"]
+    #[doc = "training), then order - 2, down to the last word, and if even that"]
+    #[doc = "was never trained it picks a word by how often it was trained. Every"]
+    #[doc = "change to the chain (training, removing or renaming words, setting"]
+    #[doc = "weights) carries over to the lower-order counts, so words removed"]
+    #[doc = "with [MarkovChain::filter_vocabulary] are never generated. An empty"]
+    #[doc = "seed_context starts from the root."]
+    pub fn backoff_generate(
+        &self,
+        seed_context: &[&str],
+        max_words: usize,
+        rng: &mut impl Rng,
+    ) -> Result<Vec<String>, MarkovError> {
+        if self.root.links.is_empty() {
+            return Err(MarkovError::EmptyChain);
+        }
+        let mut context: Vec<String> = seed_context
+            .iter()
+            .map(|word| self.normalize(word).into_owned())
+            .collect();
+        let mut out: Vec<String> = seed_context.iter().map(|word| word.to_string()).collect();
+        if context.is_empty() {
+            let first = self.step(&self.root, rng).ok_or(MarkovError::EmptyChain)?;
+            context.extend(first.data.split(' ').map(String::from));
+            out.extend(
+                context
+                    .iter()
+                    .map(|word| self.surface_form(word).to_string()),
+            );
+        }
+        while out.len() < max_words {
+            match self.backoff_step(&context, rng) {
+                Some(word) => {
+                    out.push(self.surface_form(&word).to_string());
+                    context.push(word);
+                }
+                None => break,
+            }
+        }
+        out.truncate(max_words.max(seed_context.len()));
+        Ok(out)
+    }
+    #[doc = "The backoff_step function picks the word after context from the"]
+    #[doc = "highest-order chain that knows its last words, or [None] if"]
+    #[doc = "generation should end there."]
+    fn backoff_step(&self, context: &[String], rng: &mut impl Rng) -> Option<String> {
+        let mut chain = Some(self);
+        let mut lowest = self;
+        while let Some(current) = chain {
+            lowest = current;
+            chain = current.lower_order.as_deref();
+            if context.len() < current.order() {
+                continue;
+            }
+            let state = context[context.len() - current.order()..].join(" ");
+            let node = match current.find_token(state.as_str()) {
+                Some(node) if !node.links.is_empty() => node,
+                _ => continue,
+            };
+            let next = sample_weighted(node.links.iter().map(|(id, p)| (*id, *p)), rng)?;
+            if next == current.root.id {
+                return None;
+            }
+            let next = current.node(next)?;
+            return next.data.rsplit(' ').next().map(String::from);
+        }
+        let incoming = lowest.nodes.iter().map(|node| {
+            (
+                node.id,
+                lowest.predecessor_ids(node.id).iter().map(|(_, p)| p).sum(),
+            )
+        });
+        let next = sample_weighted(incoming, rng)?;
+        lowest.node(next).map(|node| node.data.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{create_markov_chain_with_order, map_from, MarkovChainBuilder};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn nucleus_keeps_the_most_likely_links() {
+        let links = map_from(&[(1, 5), (2, 3), (3, 2)]);
+        assert_eq!(nucleus(&links, 0.1), vec![(1, 5)]);
+        assert_eq!(nucleus(&links, 0.5), vec![(1, 5)]);
+        assert_eq!(nucleus(&links, 0.6), vec![(1, 5), (2, 3)]);
+        assert_eq!(nucleus(&links, 1.0).len(), 3);
+    }
+
+    #[test]
+    fn small_p_is_greedy() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a b. a b. a c.".to_string());
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..10 {
+            let words = chain
+                .generate_nucleus(0.01, Some("a"), 2, &mut rng)
+                .unwrap();
+            assert_eq!(words, vec!["a", "b"]);
+        }
+        assert!(matches!(
+            chain.generate_nucleus(0.0, None, 5, &mut rng),
+            Err(MarkovError::InvalidConfig(_))
+        ));
+        assert_eq!(
+            chain.generate_nucleus(0.5, Some("z"), 5, &mut rng),
+            Err(MarkovError::WordNotFound("z".to_string()))
+        );
+    }
+
+    #[test]
+    fn nucleus_seeds_keep_their_surface_form() {
+        let mut chain = MarkovChain::new();
+        chain.set_normalizer(|word| word.trim_end_matches("ning").to_string());
+        chain.train_text("Running fast".to_string());
+        let mut rng = StdRng::seed_from_u64(3);
+        let words = chain
+            .generate_nucleus(1.0, Some("running"), 5, &mut rng)
+            .unwrap();
+        assert_eq!(words, ["running", "fast"]);
+        let opts = GenerationOptions {
+            start_word: Some("running".to_string()),
+            ..GenerationOptions::default()
+        };
+        assert_eq!(chain.generate_with(&opts).unwrap(), "running fast");
+    }
+
+    #[test]
+    fn unique_texts_stop_when_the_chain_runs_out() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a b".to_string());
+        chain.train_text("a c".to_string());
+        let mut rng = StdRng::seed_from_u64(1);
+        let results = chain.generate_n(5, 10, &mut rng);
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(|result| result.is_ok()));
+        let unique = chain.generate_n_unique(5, 10, &mut rng, 100);
+        assert_eq!(unique.len(), 2);
+        assert_ne!(unique[0], unique[1]);
+        let empty = MarkovChain::new();
+        assert!(empty.generate_n_unique(5, 10, &mut rng, 100).is_empty());
+    }
+
+    #[test]
+    fn backoff_continues_unseen_contexts() {
+        let mut chain = create_markov_chain_with_order(3).unwrap();
+        chain.train_text("the cat sat on the mat".to_string());
+        let mut rng = StdRng::seed_from_u64(7);
+        // "a big cat" was never trained, but "cat" was, followed by "sat"
+        let words = chain
+            .backoff_generate(&["a", "big", "cat"], 5, &mut rng)
+            .unwrap();
+        assert_eq!(words, ["a", "big", "cat", "sat", "on"]);
+        // nothing about "zebra" was trained, so a trained word is picked
+        let words = chain.backoff_generate(&["zebra"], 2, &mut rng).unwrap();
+        assert_eq!(words.len(), 2);
+        assert!(["the", "cat", "sat", "on", "mat"].contains(&words[1].as_str()));
+        assert!(chain.backoff_generate(&[], 10, &mut rng).unwrap().len() <= 10);
+        assert_eq!(
+            MarkovChain::new().backoff_generate(&["a"], 5, &mut rng),
+            Err(MarkovError::EmptyChain)
+        );
+    }
+
+    #[test]
+    fn backoff_never_generates_filtered_words() {
+        let mut chain = create_markov_chain_with_order(2).unwrap();
+        chain.train_text("the cat sat on the mat. the dog sat on the cat.".to_string());
+        chain.filter_vocabulary(&HashSet::from(["the", "cat", "sat", "on", "mat"]));
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            for context in [&[][..], &["the"], &["sat", "on"], &["zebra"]] {
+                let words = chain.backoff_generate(context, 10, &mut rng).unwrap();
+                assert!(!words.contains(&"dog".to_string()), "{words:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn sentences_stop_at_their_end() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut chain = MarkovChain::new();
+        chain.train_words(&[START_TOKEN, "hi", "there", END_TOKEN, "never"]);
+        assert_eq!(
+            chain.generate_sentence_with_rng(&mut rng).unwrap(),
+            "Hi there."
+        );
+
+        let mut chain = MarkovChain::new();
+        chain.train_text("the end".to_string());
+        assert_eq!(chain.generate_sentence().unwrap(), "The end.");
+
+        // "a" and "b" only link to each other, so the cap ends the sentence
+        let mut chain = MarkovChain::new();
+        chain.train_chars("ab");
+        chain.add_forced_transition("b", "a", 1).unwrap();
+        let b = chain.find_node("b").unwrap().id;
+        chain.remove_portions(b, chain.root.id, 1);
+        let sentence = chain.generate_sentence_with_rng(&mut rng).unwrap();
+        assert_eq!(sentence.split(' ').count(), DEFAULT_MAX_SENTENCE_WORDS);
+
+        assert_eq!(
+            MarkovChain::new().generate_sentence(),
+            Err(MarkovError::EmptyChain)
+        );
+    }
+
+    fn generate_all(chain: &MarkovChain, opts: &GenerationOptions) -> HashSet<String> {
+        (0..20)
+            .map(|seed| {
+                let opts = GenerationOptions {
+                    seed: Some(seed),
+                    ..opts.clone()
+                };
+                chain.generate_with(&opts).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn generation_options_default_to_a_sentence() {
+        let mut chain = MarkovChain::new();
+        assert_eq!(
+            chain.generate_with(&GenerationOptions::default()),
+            Err(MarkovError::EmptyChain)
+        );
+        chain.train_text("a b c".to_string());
+        assert_eq!(
+            chain.generate_with(&GenerationOptions::default()).unwrap(),
+            "a b c"
+        );
+    }
+
+    #[test]
+    fn generation_options_max_words() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a b c".to_string());
+        let opts = GenerationOptions {
+            max_words: 2,
+            ..Default::default()
+        };
+        assert_eq!(chain.generate_with(&opts).unwrap(), "a b");
+    }
+
+    #[test]
+    fn generation_options_min_words() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a. a b c.".to_string());
+        assert!(generate_all(&chain, &GenerationOptions::default()).contains("a"));
+        let opts = GenerationOptions {
+            min_words: 3,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_all(&chain, &opts),
+            HashSet::from(["a b c".to_string()])
+        );
+        let opts = GenerationOptions {
+            min_words: 4,
+            ..Default::default()
+        };
+        assert_eq!(
+            chain.generate_with(&opts),
+            Err(MarkovError::GenerationFailed {
+                attempts: MAX_GENERATION_ATTEMPTS
+            })
+        );
+        let opts = GenerationOptions {
+            min_words: 3,
+            max_words: 2,
+            ..Default::default()
+        };
+        assert!(matches!(
+            chain.generate_with(&opts),
+            Err(MarkovError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn generation_outcomes_keep_the_longest_attempt() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a. a b c.".to_string());
+        let opts = GenerationOptions {
+            min_words: 3,
+            seed: Some(0),
+            ..Default::default()
+        };
+        let outcome = chain.generate_with_outcome(&opts).unwrap();
+        assert_eq!(outcome.text, "a b c");
+        assert_eq!(outcome.words, 3);
+        assert!(outcome.satisfied);
+        assert!(outcome.attempts <= MAX_GENERATION_ATTEMPTS);
+
+        let opts = GenerationOptions {
+            min_words: 4,
+            max_retries: 9,
+            seed: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(
+            chain.generate_with_outcome(&opts).unwrap(),
+            GenerationOutcome {
+                text: "a b c".to_string(),
+                words: 3,
+                attempts: 10,
+                satisfied: false,
+            }
+        );
+        assert_eq!(
+            chain.generate_with(&opts),
+            Err(MarkovError::GenerationFailed { attempts: 10 })
+        );
+
+        let opts = GenerationOptions {
+            min_words: 4,
+            max_retries: 0,
+            ..Default::default()
+        };
+        let outcome = chain.generate_with_outcome(&opts).unwrap();
+        assert_eq!(outcome.attempts, 1);
+        assert!(outcome.text == "a" || outcome.text == "a b c");
+    }
+
+    #[test]
+    fn generation_options_start_word() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a b c".to_string());
+        let opts = GenerationOptions {
+            start_word: Some("B".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(chain.generate_with(&opts).unwrap(), "b c");
+        let opts = GenerationOptions {
+            start_word: Some("z".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            chain.generate_with(&opts),
+            Err(MarkovError::WordNotFound("z".to_string()))
+        );
+    }
+
+    #[test]
+    fn generation_options_stop_at_terminal() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a b".to_string());
+        chain.train_word("b", "c");
+        let all = generate_all(&chain, &GenerationOptions::default());
+        assert!(all.contains("a b") && all.contains("a b c"));
+        let opts = GenerationOptions {
+            stop_at_terminal: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_all(&chain, &opts),
+            HashSet::from(["a b c".to_string()])
+        );
+    }
+
+    #[test]
+    fn generation_options_seed() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a b c. a c b. b a. c a b.".to_string());
+        let opts = GenerationOptions {
+            seed: Some(7),
+            ..Default::default()
+        };
+        let first = chain.generate_with(&opts).unwrap();
+        for _ in 0..5 {
+            assert_eq!(chain.generate_with(&opts).unwrap(), first);
+        }
+        assert!(generate_all(&chain, &GenerationOptions::default()).len() > 1);
+    }
+
+    #[test]
+    fn generation_options_banned_words() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a b c. a d c.".to_string());
+        assert!(generate_all(&chain, &GenerationOptions::default()).contains("a b c"));
+        let opts = GenerationOptions {
+            banned_words: vec!["B".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_all(&chain, &opts),
+            HashSet::from(["a d c".to_string()])
+        );
+    }
+
+    #[test]
+    fn generation_options_immediate_repeats() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a a a b".to_string());
+        assert!(generate_all(&chain, &GenerationOptions::default()).contains("a a a b"));
+        let opts = GenerationOptions {
+            allow_immediate_repeat: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_all(&chain, &opts),
+            HashSet::from(["a b".to_string()])
+        );
+
+        // "really" almost always follows itself, so it stutters unless
+        // repeats are left out and "good" gets all of the probability
+        let mut chain = MarkovChain::new();
+        chain.train_text("so really good".to_string());
+        chain
+            .add_forced_transition("really", "really", 1000)
+            .unwrap();
+        let stutters = |opts: &GenerationOptions| {
+            generate_all(&chain, opts)
+                .iter()
+                .any(|text| text.contains("really really"))
+        };
+        assert!(stutters(&GenerationOptions::default()));
+        assert_eq!(
+            generate_all(&chain, &opts),
+            HashSet::from(["so really good".to_string()])
+        );
+
+        // with the terminal ruled out "z" only has itself left
+        let mut chain = MarkovChain::new();
+        chain.train_text("z z".to_string());
+        let opts = GenerationOptions {
+            max_words: 5,
+            stop_at_terminal: false,
+            ..Default::default()
+        };
+        assert_eq!(chain.generate_with(&opts).unwrap(), "z z z z z");
+        let opts = GenerationOptions {
+            allow_immediate_repeat: false,
+            ..opts
+        };
+        assert_eq!(chain.generate_with(&opts).unwrap(), "z");
+    }
+
+    #[test]
+    fn temperature_weights_stay_finite() {
+        let choices = [(1, 6), (2, 3), (3, 1)];
+        assert_eq!(
+            temperature_weights(&choices, 0.0),
+            [(1, 1.0), (2, 0.0), (3, 0.0)]
+        );
+        assert_eq!(
+            temperature_weights(&choices, 1.0),
+            [(1, 1.0), (2, 0.5), (3, 1.0 / 6.0)]
+        );
+        for temperature in [1e-300, 0.01, 2.0, 1e300, f64::INFINITY] {
+            let weights = temperature_weights(&choices, temperature);
+            assert!(weights
+                .iter()
+                .all(|(_, weight)| weight.is_finite() && *weight >= 0.0));
+            assert_eq!(weights[0].1, 1.0);
+        }
+        assert_eq!(
+            temperature_weights(&choices, f64::INFINITY),
+            [(1, 1.0), (2, 1.0), (3, 1.0)]
+        );
+        assert_eq!(temperature_weights(&[(4, 2)], 1e-300), [(4, 1.0)]);
+        assert_eq!(temperature_weights(&[(4, 2)], 0.0), [(4, 1.0)]);
+    }
+
+    #[test]
+    fn generation_options_temperature() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a b. a b. a b. a c.".to_string());
+        let opts = GenerationOptions {
+            start_word: Some("a".to_string()),
+            temperature: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_all(&chain, &opts),
+            HashSet::from(["a b".to_string()])
+        );
+        let opts = GenerationOptions {
+            temperature: f64::INFINITY,
+            ..opts
+        };
+        assert_eq!(generate_all(&chain, &opts).len(), 2);
+        let opts = GenerationOptions {
+            temperature: -1.0,
+            ..opts
+        };
+        assert!(matches!(
+            chain.generate_with(&opts),
+            Err(MarkovError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn most_likely_path_beats_greedy() {
+        let mut chain = MarkovChain::new();
+        // greedy picks a b (0.6) then d or e (0.5), but a c f is 0.4
+        chain.train_pairs(&[
+            ("a", "b", 3),
+            ("a", "c", 2),
+            ("b", "d", 1),
+            ("b", "e", 1),
+            ("c", "f", 1),
+        ]);
+        assert_eq!(chain.most_likely_path("A", 2).unwrap(), ["a", "c", "f"]);
+        assert_eq!(chain.most_likely_path("a", 1).unwrap(), ["a", "b"]);
+        assert_eq!(chain.most_likely_path("a", 5).unwrap(), ["a", "c", "f"]);
+        assert_eq!(chain.most_likely_path("a", 0).unwrap(), ["a"]);
+        assert_eq!(chain.most_likely_path("b", 1).unwrap(), ["b", "d"]);
+        assert_eq!(
+            chain.most_likely_path("z", 1),
+            Err(MarkovError::WordNotFound("z".to_string()))
+        );
+    }
+
+    #[test]
+    fn generation_options_bound_cycles() {
+        let mut chain = MarkovChain::new();
+        chain.train_pairs(&[("a", "b", 1), ("b", "a", 1)]);
+        let opts = GenerationOptions {
+            start_word: Some("a".to_string()),
+            max_words: 7,
+            max_visits: None,
+            ..Default::default()
+        };
+        assert_eq!(chain.generate_with(&opts).unwrap(), "a b a b a b a");
+        let opts = GenerationOptions {
+            max_visits: Some(2),
+            ..opts
+        };
+        assert_eq!(chain.generate_with(&opts).unwrap(), "a b a b");
+        let opts = GenerationOptions {
+            max_visits: Some(0),
+            ..opts
+        };
+        assert!(matches!(
+            chain.generate_with(&opts),
+            Err(MarkovError::InvalidConfig(_))
+        ));
+        let opts = GenerationOptions {
+            start_word: Some("a".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            chain.generate_with(&opts).unwrap().split(' ').count(),
+            2 * DEFAULT_MAX_VISITS
+        );
+    }
+
+    #[test]
+    fn generation_options_resample_after_max_visits() {
+        let mut chain = MarkovChain::new();
+        chain.train_pairs(&[("a", "a", 1000), ("a", "b", 1)]);
+        let opts = GenerationOptions {
+            start_word: Some("a".to_string()),
+            max_visits: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_all(&chain, &opts),
+            HashSet::from(["a a b".to_string()])
+        );
+    }
+
+    #[test]
+    fn sentences_and_paragraphs() {
+        let mut chain = MarkovChain::new();
+        assert!(chain.generate_sentences(3).is_empty());
+        assert_eq!(chain.generate_paragraph(3), "");
+        chain.train_text("the cat sat. a dog ran! the dog sat.".to_string());
+        let sentences = chain.generate_sentences_with_rng(5, &mut StdRng::seed_from_u64(1));
+        assert_eq!(sentences.len(), 5);
+        assert_eq!(
+            sentences,
+            chain.generate_sentences_with_rng(5, &mut StdRng::seed_from_u64(1))
+        );
+        assert!(sentences.iter().collect::<HashSet<_>>().len() > 1);
+
+        let mut chain = MarkovChain::new();
+        chain.train_text("hello world".to_string());
+        assert_eq!(chain.generate_paragraph(2), "Hello world. Hello world.");
+        chain.train_words(&[START_TOKEN, "really", "?", END_TOKEN]);
+        let paragraph = chain.generate_paragraph_with_rng(20, &mut StdRng::seed_from_u64(2));
+        assert!(paragraph.contains("Really?"));
+        assert!(!paragraph.contains("?."));
+    }
+
+    #[test]
+    fn beam_search_finds_likelier_texts_than_greedy() {
+        let mut chain = MarkovChain::new();
+        // s a then x, y or z is 0.6 * 1/3 = 0.2, s b c is 0.4 * 1 = 0.4
+        chain.train_text("s a x. s a y. s a z. s b c. s b c.".to_string());
+        assert_eq!(
+            chain.generate_beam(1, 10, Some("s")).unwrap(),
+            ["s", "a", "x"]
+        );
+        assert_eq!(
+            chain.generate_beam(2, 10, Some("S")).unwrap(),
+            ["s", "b", "c"]
+        );
+        assert_eq!(chain.generate_beam(3, 10, None).unwrap(), ["s", "b", "c"]);
+        assert_eq!(chain.generate_beam(2, 2, Some("s")).unwrap(), ["s", "a"]);
+        assert_eq!(
+            chain.generate_beam(2, 0, None).unwrap(),
+            Vec::<String>::new()
+        );
+        assert!(matches!(
+            chain.generate_beam(0, 10, None),
+            Err(MarkovError::InvalidConfig(_))
+        ));
+        assert_eq!(
+            chain.generate_beam(2, 10, Some("q")),
+            Err(MarkovError::WordNotFound("q".to_string()))
+        );
+        assert_eq!(
+            MarkovChain::new().generate_beam(2, 10, None),
+            Err(MarkovError::EmptyChain)
+        );
+    }
+
+    #[test]
+    fn banned_words_never_appear() {
+        let mut chain = MarkovChain::new();
+        chain.train_pairs(&[
+            ("you", "jerk", 1000),
+            ("you", "friend", 1),
+            ("you", "pal", 1),
+            ("jerk", "you", 1000),
+            ("friend", "you", 1),
+            ("pal", "you", 1),
+        ]);
+        chain.train_pairs(&[("hey", "jerk", 1)]);
+        let mut opts = GenerationOptions {
+            start_word: Some("you".to_string()),
+            max_words: 20,
+            max_visits: None,
+            banned_words: vec!["JERK".to_string()],
+            ..Default::default()
+        };
+        for seed in 0..1000 {
+            opts.seed = Some(seed);
+            let text = chain.generate_with(&opts).unwrap();
+            assert!(!text.split(' ').any(|word| word == "jerk"), "{text}");
+            assert_eq!(text.split(' ').count(), 20);
+        }
+        // every link out of hey is banned, so the walk ends right away
+        opts.start_word = Some("hey".to_string());
+        assert_eq!(chain.generate_with(&opts).unwrap(), "hey");
+    }
+
+    #[test]
+    fn novelty_check_rejects_training_sentences() {
+        let text = "the cat sat on the mat. the dog sat on the rug.";
+        let mut chain = MarkovChain::new();
+        chain.train_text(text.to_string());
+        let sentences: HashSet<String> = (0..200)
+            .map(|seed| {
+                chain
+                    .generate_sentence_with_rng(&mut StdRng::seed_from_u64(seed))
+                    .unwrap()
+            })
+            .collect();
+        assert!(sentences.contains("The cat sat on the mat."));
+
+        let mut chain = MarkovChainBuilder::new().novelty_check(3).build().unwrap();
+        chain.train_text(text.to_string());
+        let trained = [
+            ["the", "cat", "sat", "on", "the", "mat"],
+            ["the", "dog", "sat", "on", "the", "rug"],
+        ];
+        let windows: HashSet<&[&str]> = trained.iter().flat_map(|words| words.windows(4)).collect();
+        for seed in 0..200 {
+            let sentence = chain
+                .generate_sentence_with_rng(&mut StdRng::seed_from_u64(seed))
+                .unwrap();
+            let sentence = sentence.to_lowercase();
+            let words: Vec<&str> = sentence.trim_end_matches('.').split(' ').collect();
+            assert!(
+                !words.windows(4).any(|window| windows.contains(window)),
+                "{sentence}"
+            );
+        }
+
+        let mut chain = MarkovChainBuilder::new().novelty_check(1).build().unwrap();
+        chain.train_text("only one way".to_string());
+        assert_eq!(
+            chain.generate_sentence(),
+            Err(MarkovError::GenerationFailed {
+                attempts: MAX_GENERATION_ATTEMPTS
+            })
+        );
+        assert!(matches!(
+            MarkovChainBuilder::new().novelty_check(0).build(),
+            Err(MarkovError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn walks_are_lazy_iterators() {
+        let mut chain = MarkovChain::new();
+        assert_eq!(chain.walk().next(), None);
+        chain.train_text("a b c".to_string());
+        assert_eq!(chain.walk().collect::<Vec<_>>(), ["a", "b", "c"]);
+        assert_eq!(
+            chain.walk_from("B").unwrap().collect::<Vec<_>>(),
+            ["b", "c"]
+        );
+        assert_eq!(
+            chain.walk_from("z").err(),
+            Some(MarkovError::WordNotFound("z".to_string()))
+        );
+
+        chain.add_forced_transition("c", "a", 1).unwrap();
+        let words: Vec<String> = chain
+            .walk_with_rng(StdRng::seed_from_u64(5))
+            .take(50)
+            .collect();
+        assert!(words.len() <= 50);
+        assert!(words.starts_with(&["a".to_string(), "b".to_string(), "c".to_string()]));
+
+        let mut chain = create_markov_chain_with_order(2).unwrap();
+        chain.train_text("the cat sat".to_string());
+        assert_eq!(chain.walk().collect::<Vec<_>>(), ["the", "cat", "sat"]);
+    }
+
+    #[test]
+    fn sentence_streams_are_seeded_and_never_empty() {
+        let mut chain = MarkovChain::new();
+        assert_eq!(chain.sentences(0).next(), None);
+        chain.train_text("the cat sat. a dog ran! the dog sat.".to_string());
+        // a sentence that is only the end token would be empty
+        chain.train_words(&[START_TOKEN, END_TOKEN]);
+        let sentences: Vec<String> = chain.sentences(3).take(1000).collect();
+        assert_eq!(sentences.len(), 1000);
+        assert!(sentences.iter().all(|sentence| !sentence.is_empty()));
+        assert_eq!(sentences, chain.sentences(3).take(1000).collect::<Vec<_>>());
+        assert_ne!(
+            chain.sentences(3).take(20).collect::<Vec<_>>(),
+            chain.sentences(4).take(20).collect::<Vec<_>>()
+        );
+
+        let mut chain = MarkovChain::new();
+        chain.train_words(&[START_TOKEN, END_TOKEN]);
+        assert_eq!(chain.sentences(0).next(), None);
+    }
+
+    #[test]
+    fn generate_until_stops_on_the_predicate() {
+        let mut chain = MarkovChain::new();
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(
+            chain.generate_until(|_| true, &mut rng, 10),
+            Err(MarkovError::EmptyChain)
+        );
+        chain.train_text("a b c d e".to_string());
+        assert_eq!(
+            chain
+                .generate_until(|words| words.last().unwrap() == "c", &mut rng, 10)
+                .unwrap(),
+            ["a", "b", "c"]
+        );
+        let mut lengths = Vec::new();
+        let words = chain
+            .generate_until(
+                |words| {
+                    lengths.push(words.len());
+                    false
+                },
+                &mut rng,
+                4,
+            )
+            .unwrap();
+        assert_eq!(words, ["a", "b", "c", "d"]);
+        assert_eq!(lengths, [1, 2, 3, 4]);
+        assert_eq!(
+            chain.generate_until(|_| false, &mut rng, 10).unwrap(),
+            ["a", "b", "c", "d", "e"]
+        );
+    }
+
+    #[test]
+    fn sentences_are_capitalized_and_punctuated() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("i think so".to_string());
+        assert_eq!(chain.generate_sentence().unwrap(), "I think so.");
+        let mut opts = GenerationOptions::default();
+        assert_eq!(chain.generate_with(&opts).unwrap(), "i think so");
+        opts.format_sentence = true;
+        assert_eq!(chain.generate_with(&opts).unwrap(), "I think so.");
+        let words = ["well", "i", "never", "!"].map(String::from);
+        assert_eq!(chain.format_sentence(&words), "Well I never!");
+        assert_eq!(chain.format_sentence(&[]), "");
+
+        let mut chain = MarkovChain::new();
+        chain.train_words(&["is", "it", "?"]);
+        assert_eq!(chain.generate_sentence().unwrap(), "Is it?");
+
+        let mut chain = MarkovChainBuilder::new()
+            .case_sensitive(true)
+            .build()
+            .unwrap();
+        chain.train_text("iPhones are made by Apple".to_string());
+        assert_eq!(
+            chain.generate_sentence().unwrap(),
+            "iPhones are made by Apple."
+        );
+
+        let mut chain = MarkovChain::new();
+        chain.set_normalizer(|word| word.trim_end_matches('s').to_string());
+        chain.train_text("cats sleep".to_string());
+        assert_eq!(chain.generate_sentence().unwrap(), "Cats sleep.");
+    }
+}
@@ -0,0 +1,18 @@
+// the C ABI in ffi.rs needs unsafe code, everything else stays safe
+#![cfg_attr(not(feature = "ffi"), forbid(unsafe_code))]
+#![cfg_attr(feature = "ffi", deny(unsafe_code))]
+
+mod analysis;
+pub mod error;
+mod export;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod generate;
+pub mod probability;
+pub mod shared;
+pub mod tokenizer;
+pub mod types;
+pub mod util;
+mod validate;
+#[cfg(feature = "wasm-bindgen")]
+pub mod wasm;
@@ -1,6 +1,3 @@
-mod types;
-mod util;
-
 fn main() {
     println!("Hello, world!");
 }
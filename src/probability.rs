@@ -0,0 +1,357 @@
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use crate::error::MarkovError;
+use crate::types::{Map, MarkovChain, MarkovNode, MarkovNodeID};
+
+#[doc = "The SmoothingStrategy enum decides what probability a [MarkovChain]"]
+#[doc = "gives to transitions that were never trained."]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SmoothingStrategy {
+    #[default]
+    None, // untrained transitions have a probability of 0
+    Laplace(f64), // add-k smoothing, k is added to the portions of every transition
+    Uniform,      // untrained transitions fall back to 1 / the number of words
+    KneserNey {
+        discount: f64,
+    }, // interpolated kneser-ney, discount is taken off every trained transition
+}
+
+impl SmoothingStrategy {
+    pub(crate) fn validate(&self) -> Result<(), MarkovError> {
+        match self {
+            SmoothingStrategy::Laplace(k) if !(k.is_finite() && *k > 0.0) => {
+                Err(MarkovError::InvalidConfig(format!(
+                    "laplace smoothing needs a positive k, got {k}"
+                )))
+            }
+            SmoothingStrategy::KneserNey { discount }
+                if !(discount.is_finite() && *discount > 0.0 && *discount <= 1.0) =>
+            {
+                Err(MarkovError::InvalidConfig(format!(
+                    "kneser-ney smoothing needs a discount above 0 and at most 1, got {discount}"
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[doc = "The successor_distribution function returns the probability of each"]
+#[doc = "word the node links to, keyed by the word."]
+pub(crate) fn successor_distribution<'a>(
+    chain: &'a MarkovChain,
+    node: &MarkovNode,
+) -> HashMap<&'a str, f64> {
+    let total: u64 = node.links.iter().map(|(_, portions)| *portions).sum();
+    let mut out = HashMap::new();
+    if total == 0 {
+        return out;
+    }
+    for (to, portions) in node.links.iter() {
+        if let Some(to) = chain.node(*to) {
+            out.insert(to.data.as_str(), *portions as f64 / total as f64);
+        }
+    }
+    out
+}
+
+#[doc = "The state_distributions function returns the successor distribution of"]
+#[doc = "every node with links (the root is keyed by [None]), along with how"]
+#[doc = "much of the chain's portions start at that node."]
+fn state_distributions(chain: &MarkovChain) -> HashMap<Option<&str>, (f64, HashMap<&str, f64>)> {
+    let total = chain.all_portions().max(1) as f64;
+    std::iter::once(&chain.root)
+        .chain(chain.nodes.iter())
+        .filter(|node| !node.links.is_empty())
+        .map(|node| {
+            let key = if node.id == chain.root.id {
+                None
+            } else {
+                Some(node.data.as_str())
+            };
+            let weight = node
+                .links
+                .iter()
+                .map(|(_, portions)| *portions)
+                .sum::<u64>() as f64;
+            (key, (weight / total, successor_distribution(chain, node)))
+        })
+        .collect()
+}
+
+#[doc = "The jensen_shannon function returns the Jensen-Shannon divergence (in"]
+#[doc = "bits, so between 0 and 1) between two distributions over the same words."]
+fn jensen_shannon(p: &HashMap<&str, f64>, q: &HashMap<&str, f64>) -> f64 {
+    let words: HashSet<&str> = p.keys().chain(q.keys()).copied().collect();
+    let mut out = 0.0;
+    for word in words {
+        let p = p.get(word).copied().unwrap_or(0.0);
+        let q = q.get(word).copied().unwrap_or(0.0);
+        let m = (p + q) / 2.0;
+        if p > 0.0 {
+            out += 0.5 * p * (p / m).log2();
+        }
+        if q > 0.0 {
+            out += 0.5 * q * (q / m).log2();
+        }
+    }
+    out
+}
+
+impl<T: Eq + Hash + Clone> MarkovChain<T> {
+    #[doc = "The get_probability function returns the probability that"]
+    #[doc = "generation goes from one word to the other. Trained transitions use"]
+    #[doc = "their share of the from word's portions, untrained ones (including"]
+    #[doc = "unknown words) are handled by the chain's [SmoothingStrategy]."]
+    #[doc = "[SmoothingStrategy::Laplace] and [SmoothingStrategy::KneserNey] are"]
+    #[doc = "applied to every transition so each word's probabilities still add"]
+    #[doc = "up to 1. Kneser-Ney gives the discounted portions to words in"]
+    #[doc = "proportion to how many distinct words they follow (see"]
+    #[doc = "[MarkovChain::continuation_count]), so words that were never trained"]
+    #[doc = "still have a probability of 0."]
+    pub fn get_probability<Q>(&self, from: &Q, to: &Q) -> f64
+    where
+        T: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let from = self.find_token(from);
+        let to = self.find_token(to);
+        let total: u64 = from
+            .map(|node| node.links.iter().map(|(_, portions)| *portions).sum())
+            .unwrap_or(0);
+        let count = match (from, to) {
+            (Some(from), Some(to)) => from.links.get(to.id).unwrap_or(0),
+            _ => 0,
+        };
+        let vocabulary = self.nodes.len().max(1) as f64;
+        match self.smoothing() {
+            SmoothingStrategy::Laplace(k) => (count as f64 + k) / (total as f64 + k * vocabulary),
+            SmoothingStrategy::KneserNey { discount } => {
+                let continuation = self.continuation_probability(to.map(|to| to.id));
+                match from {
+                    Some(from) if total > 0 => {
+                        let kept = (count as f64 - discount).max(0.0) / total as f64;
+                        let lambda = discount * from.links.len() as f64 / total as f64;
+                        kept + lambda * continuation
+                    }
+                    _ => continuation,
+                }
+            }
+            _ if count > 0 => count as f64 / total as f64,
+            SmoothingStrategy::None => 0.0,
+            SmoothingStrategy::Uniform => 1.0 / vocabulary,
+        }
+    }
+    #[doc = "The continuation_probability function returns the share of all the"]
+    #[doc = "chain's distinct links that go into the node with the provided id,"]
+    #[doc = "which is 0 for [None] and an empty chain."]
+    fn continuation_probability(&self, id: Option<MarkovNodeID>) -> f64 {
+        let links: u64 = self.continuation_counts.values().sum();
+        match id {
+            Some(id) if links > 0 => self.continuation_count(id) as f64 / links as f64,
+            _ => 0.0,
+        }
+    }
+}
+
+impl MarkovChain {
+    #[doc = "The probability function is [MarkovChain::get_probability] for"]
+    #[doc = "words as a user would type them: both are normalized the same way as"]
+    #[doc = "[MarkovChain::find_node] normalizes them first."]
+    pub fn probability(&self, from: &str, to: &str) -> f64 {
+        self.get_probability(self.normalize(from).as_ref(), self.normalize(to).as_ref())
+    }
+    #[doc = "The predict_next_distribution function returns the probability of"]
+    #[doc = "every word that can come after the provided word (normalized like"]
+    #[doc = "[MarkovChain::find_node] does), adding up to 1. In higher-order"]
+    #[doc = "chains the keys are the next single word, and the end of generation"]
+    #[doc = "is keyed by an empty string. A word with no links gives an empty map,"]
+    #[doc = "and a word that was never trained gives [MarkovError::WordNotFound]."]
+    pub fn predict_next_distribution(&self, word: &str) -> Result<Map<String, f64>, MarkovError> {
+        let node = self
+            .find_node(word)
+            .ok_or_else(|| MarkovError::WordNotFound(word.to_string()))?;
+        let mut out = Map::new();
+        for (next, probability) in successor_distribution(self, node) {
+            let next = next.rsplit(' ').next().unwrap_or(next);
+            *out.get_or_insert(next.to_string(), 0.0) += probability;
+        }
+        Ok(out)
+    }
+    #[doc = "The log_likelihood function returns the sum of the natural log of"]
+    #[doc = "[MarkovChain::get_probability] for every consecutive pair of nodes in"]
+    #[doc = "the text, tokenized the same way as [MarkovChain::train_text]. With"]
+    #[doc = "[SmoothingStrategy::None] a single untrained pair makes the result"]
+    #[doc = "[f64::NEG_INFINITY], use another strategy to keep it finite."]
+    pub fn log_likelihood(&self, text: &str) -> f64 {
+        let contexts = self.contexts(&self.tokenize(text));
+        contexts
+            .windows(2)
+            .map(|pair| self.get_probability(&pair[0], &pair[1]).ln())
+            .sum()
+    }
+    #[doc = "The perplexity function returns exp(-log_likelihood / pairs), where"]
+    #[doc = "pairs is the number of consecutive pairs of nodes in the text. Lower"]
+    #[doc = "is better, untrained pairs make it [f64::INFINITY] with"]
+    #[doc = "[SmoothingStrategy::None], and text too short for a single pair has"]
+    #[doc = "a perplexity of 1."]
+    pub fn perplexity(&self, text: &str) -> f64 {
+        let pairs = self.contexts(&self.tokenize(text)).len().saturating_sub(1);
+        if pairs == 0 {
+            return 1.0;
+        }
+        (-self.log_likelihood(text) / pairs as f64).exp()
+    }
+    #[doc = "The divergence function measures how different the transitions of"]
+    #[doc = "two chains are, from 0 (identical) to 1 (nothing in common). It is the"]
+    #[doc = "Jensen-Shannon divergence of each word's (and the root's) successors,"]
+    #[doc = "averaged by how much of each chain's portions start at that word. A"]
+    #[doc = "word that only has links in one chain is compared against a uniform"]
+    #[doc = "distribution over every word of both chains (what add-k smoothing"]
+    #[doc = "gives a word with no portions), so the result is always finite."]
+    pub fn divergence(&self, other: &MarkovChain) -> f64 {
+        let ours = state_distributions(self);
+        let theirs = state_distributions(other);
+        let states: HashSet<Option<&str>> = ours.keys().chain(theirs.keys()).copied().collect();
+        let vocabulary: HashSet<&str> = self
+            .nodes
+            .iter()
+            .chain(other.nodes.iter())
+            .map(|node| node.data.as_str())
+            .collect();
+        let uniform = 1.0 / vocabulary.len().max(1) as f64;
+        let uniform: HashMap<&str, f64> =
+            vocabulary.into_iter().map(|word| (word, uniform)).collect();
+        let mut out = 0.0;
+        for state in states {
+            let (ours, theirs) = match (ours.get(&state), theirs.get(&state)) {
+                (Some(ours), Some(theirs)) => (ours.clone(), theirs.clone()),
+                (Some(ours), None) => (ours.clone(), (0.0, uniform.clone())),
+                (None, Some(theirs)) => ((0.0, uniform.clone()), theirs.clone()),
+                (None, None) => continue,
+            };
+            out += (ours.0 + theirs.0) / 2.0 * jensen_shannon(&ours.1, &theirs.1);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SmoothingStrategy;
+    use crate::error::MarkovError;
+    use crate::types::MarkovChainBuilder;
+
+    #[test]
+    fn smoothing_decides_untrained_probabilities() {
+        let mut chain = MarkovChainBuilder::new().build().unwrap();
+        chain.train_text("a b a c".to_string());
+        assert_eq!(chain.get_probability("a", "b"), 0.5);
+        assert_eq!(chain.get_probability("b", "c"), 0.0);
+        assert_eq!(chain.probability("A", "B"), 0.5);
+
+        chain.set_smoothing(SmoothingStrategy::Uniform).unwrap();
+        assert_eq!(chain.get_probability("a", "b"), 0.5);
+        assert_eq!(chain.get_probability("b", "c"), 1.0 / 3.0);
+
+        // "a" has 2 portions over a vocabulary of 3 words
+        chain
+            .set_smoothing(SmoothingStrategy::Laplace(1.0))
+            .unwrap();
+        assert_eq!(chain.get_probability("a", "b"), 2.0 / 5.0);
+        assert_eq!(chain.get_probability("a", "a"), 1.0 / 5.0);
+    }
+
+    #[test]
+    fn kneser_ney_discounts_towards_continuations() {
+        let mut chain = MarkovChainBuilder::new()
+            .smoothing(SmoothingStrategy::KneserNey { discount: 0.5 })
+            .build()
+            .unwrap();
+        // links: root -> a, a -> b, b -> a, a -> c, c -> end (the root)
+        chain.train_text("a b a c".to_string());
+        let a = chain.find_node("a").unwrap().id;
+        assert_eq!(chain.continuation_count(a), 2);
+        assert!((chain.get_probability("a", "b") - 0.35).abs() < 1e-12);
+        assert!((chain.get_probability("a", "a") - 0.2).abs() < 1e-12);
+        assert!((chain.get_probability("b", "c") - 0.1).abs() < 1e-12);
+        assert!((chain.get_probability("z", "a") - 0.4).abs() < 1e-12);
+        assert_eq!(chain.get_probability("a", "z"), 0.0);
+        assert!(chain.perplexity("b c").is_finite());
+
+        chain.set_transition_weight("b", "a", 0).unwrap();
+        assert_eq!(chain.continuation_count(a), 1);
+        assert_eq!(
+            chain.set_smoothing(SmoothingStrategy::KneserNey { discount: 1.5 }),
+            Err(MarkovError::InvalidConfig(
+                "kneser-ney smoothing needs a discount above 0 and at most 1, got 1.5".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn perplexity_of_untrained_text() {
+        let mut chain = MarkovChainBuilder::new().build().unwrap();
+        chain.train_text("a b a c".to_string());
+        assert_eq!(chain.log_likelihood("A b, a c!"), 0.5f64.ln() * 2.0);
+        assert_eq!(
+            chain.perplexity("a b a c"),
+            (-(0.5f64.ln() * 2.0) / 3.0).exp()
+        );
+        assert_eq!(chain.log_likelihood("b c"), f64::NEG_INFINITY);
+        assert_eq!(chain.perplexity("b c"), f64::INFINITY);
+        assert_eq!(chain.perplexity("a"), 1.0);
+
+        chain
+            .set_smoothing(SmoothingStrategy::Laplace(1.0))
+            .unwrap();
+        assert!(chain.perplexity("b c").is_finite());
+    }
+
+    #[test]
+    fn divergence_of_chains() {
+        let mut a = MarkovChainBuilder::new().build().unwrap();
+        a.train_text("the cat sat on the mat".to_string());
+        let mut b = MarkovChainBuilder::new().build().unwrap();
+        b.train_text("the cat sat on the mat".to_string());
+        assert_eq!(a.divergence(&b), 0.0);
+
+        let mut c = MarkovChainBuilder::new().build().unwrap();
+        c.train_text("dogs bark loudly".to_string());
+        let far = a.divergence(&c);
+        assert!(far.is_finite() && far > 0.5 && far <= 1.0);
+        assert!((far - c.divergence(&a)).abs() < 1e-12);
+
+        b.train_text("the dog sat".to_string());
+        let near = a.divergence(&b);
+        assert!(near > 0.0 && near < far);
+    }
+
+    #[test]
+    fn next_distribution_adds_up_to_one() {
+        let mut chain = MarkovChainBuilder::new().build().unwrap();
+        chain.train_text("a b a c a".to_string());
+        let next = chain.predict_next_distribution("A").unwrap();
+        assert_eq!(next.len(), 3);
+        assert!((next.get("b".to_string()).unwrap() - 1.0 / 3.0).abs() < 1e-12);
+        assert!((next.get(String::new()).unwrap() - 1.0 / 3.0).abs() < 1e-12);
+        let total: f64 = next.iter().map(|(_, p)| p).sum();
+        assert!((total - 1.0).abs() < 1e-12);
+        chain.train_word("sink", "d");
+        assert!(chain.predict_next_distribution("d").unwrap().is_empty());
+        assert_eq!(
+            chain.predict_next_distribution("z").unwrap_err(),
+            MarkovError::WordNotFound("z".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_laplace_k_is_rejected() {
+        let built = MarkovChainBuilder::new()
+            .smoothing(SmoothingStrategy::Laplace(-1.0))
+            .build();
+        assert!(built.is_err());
+    }
+}
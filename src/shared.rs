@@ -0,0 +1,114 @@
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use rand::Rng;
+
+use crate::error::MarkovError;
+use crate::types::MarkovChain;
+
+#[doc = "The SharedMarkovChain struct shares a [MarkovChain] between threads"]
+#[doc = "that mostly generate and sometimes train. Generating only takes a read"]
+#[doc = "lock, so any number of threads can generate at once, and training"]
+#[doc = "takes the write lock. Cloning it gives another handle to the same"]
+#[doc = "chain. Readers that shouldn't wait on training at all can take a"]
+#[doc = "[SharedMarkovChain::snapshot] instead."]
+#[derive(Clone, Debug)]
+pub struct SharedMarkovChain {
+    chain: Arc<RwLock<MarkovChain>>,
+}
+
+impl From<MarkovChain> for SharedMarkovChain {
+    fn from(chain: MarkovChain) -> SharedMarkovChain {
+        SharedMarkovChain::new(chain)
+    }
+}
+
+impl SharedMarkovChain {
+    pub fn new(chain: MarkovChain) -> SharedMarkovChain {
+        SharedMarkovChain {
+            chain: Arc::new(RwLock::new(chain)),
+        }
+    }
+    #[doc = "The read function takes the read lock, for calling any of the"]
+    #[doc = "chain's &self methods. It panics if a thread panicked while holding"]
+    #[doc = "the write lock, since the chain might be half trained."]
+    pub fn read(&self) -> RwLockReadGuard<'_, MarkovChain> {
+        self.chain
+            .read()
+            .expect("a thread panicked while training the shared chain")
+    }
+    #[doc = "The write function takes the write lock, for training or changing"]
+    #[doc = "the chain in any other way. It panics like [SharedMarkovChain::read]."]
+    pub fn write(&self) -> RwLockWriteGuard<'_, MarkovChain> {
+        self.chain
+            .write()
+            .expect("a thread panicked while training the shared chain")
+    }
+    #[doc = "The generate function is [MarkovChain::generate] under the read lock."]
+    pub fn generate(&self, max_words: usize) -> Result<Vec<String>, MarkovError> {
+        self.read().generate(max_words)
+    }
+    #[doc = "The generate_text function is [MarkovChain::generate_text] under the"]
+    #[doc = "read lock."]
+    pub fn generate_text(&self, max_words: usize) -> Result<String, MarkovError> {
+        self.read().generate_text(max_words)
+    }
+    #[doc = "The generate_with_rng function is [MarkovChain::generate_with_rng]"]
+    #[doc = "under the read lock."]
+    pub fn generate_with_rng<R: Rng>(
+        &self,
+        rng: &mut R,
+        max_words: usize,
+    ) -> Result<Vec<String>, MarkovError> {
+        self.read().generate_with_rng(rng, max_words)
+    }
+    #[doc = "The train_text function is [MarkovChain::train_text] under the"]
+    #[doc = "write lock."]
+    pub fn train_text(&self, text: String) {
+        self.write().train_text(text);
+    }
+    #[doc = "The train_text_weighted function is"]
+    #[doc = "[MarkovChain::train_text_weighted] under the write lock."]
+    pub fn train_text_weighted(&self, text: &str, weight: u64) {
+        self.write().train_text_weighted(text, weight);
+    }
+    #[doc = "The train_word function is [MarkovChain::train_word] under the"]
+    #[doc = "write lock."]
+    pub fn train_word(&self, from_word: &str, to_word: &str) {
+        self.write().train_word(from_word, to_word);
+    }
+    #[doc = "The snapshot function returns a copy of the chain as it is now,"]
+    #[doc = "which can be read without any locking. Training done after the"]
+    #[doc = "snapshot isn't seen by it."]
+    pub fn snapshot(&self) -> Arc<MarkovChain> {
+        Arc::new(self.read().clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threads_generate_while_another_trains() {
+        let shared = SharedMarkovChain::from(MarkovChain::new());
+        shared.train_text("hello there".to_string());
+        let snapshot = shared.snapshot();
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let shared = shared.clone();
+                std::thread::spawn(move || {
+                    if i == 0 {
+                        shared.train_text("general kenobi".to_string());
+                    }
+                    shared.generate(10).unwrap()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert!(!handle.join().unwrap().is_empty());
+        }
+        assert_eq!(shared.read().nodes.len(), 4);
+        assert_eq!(snapshot.nodes.len(), 2);
+        assert_eq!(snapshot.generate(10).unwrap(), ["hello", "there"]);
+    }
+}
@@ -0,0 +1,322 @@
+use std::fmt;
+
+use regex::Regex;
+
+#[doc = "The Tokenizer trait splits text into the words a"]
+#[doc = "[crate::types::MarkovChain] is trained on. Set the one a chain uses"]
+#[doc = "with [crate::types::MarkovChain::set_tokenizer], or use one for a"]
+#[doc = "single text with [crate::types::MarkovChain::train_text_with]."]
+#[doc = "Tokenizers keep the case of the text, the chain lowercases the words"]
+#[doc = "afterwards unless it is case-sensitive."]
+pub trait Tokenizer: fmt::Debug + Send + Sync {
+    fn tokenize(&self, text: &str) -> Vec<String>;
+}
+
+#[doc = "The SimpleTokenizer struct is the default [Tokenizer]: it removes"]
+#[doc = "everything that isn't a letter, digit, underscore, apostrophe, hyphen"]
+#[doc = "or whitespace, splits on whitespace, and then strips apostrophes and"]
+#[doc = "hyphens from the start and end of each word. \"Don't!\" becomes"]
+#[doc = "\"Don't\", \"well-known\" stays as it is and \"'n'\" becomes \"n\"."]
+#[derive(Clone, Debug)]
+pub struct SimpleTokenizer {
+    cleanup: Regex,
+    trim: bool, // strip apostrophes and hyphens from the ends of each word
+}
+
+impl Default for SimpleTokenizer {
+    fn default() -> SimpleTokenizer {
+        SimpleTokenizer {
+            cleanup: Regex::new(r"(?m)[^\w\s'’-]").unwrap(),
+            trim: true,
+        }
+    }
+}
+
+impl SimpleTokenizer {
+    #[doc = "The with_pattern function creates a [SimpleTokenizer] that removes"]
+    #[doc = "whatever the provided regex matches, and nothing else (words aren't"]
+    #[doc = "trimmed), returning the regex's error if it is invalid."]
+    pub fn with_pattern(pattern: &str) -> Result<SimpleTokenizer, regex::Error> {
+        Ok(SimpleTokenizer {
+            cleanup: Regex::new(pattern)?,
+            trim: false,
+        })
+    }
+    #[doc = "The aggressive function creates a [SimpleTokenizer] that removes"]
+    #[doc = "everything that isn't a letter, digit, underscore or whitespace,"]
+    #[doc = "apostrophes and hyphens included, so \"can't\" becomes \"cant\" and"]
+    #[doc = "\"well-known\" becomes \"wellknown\". This was the default before"]
+    #[doc = "contractions and hyphenated words were kept."]
+    pub fn aggressive() -> SimpleTokenizer {
+        SimpleTokenizer {
+            cleanup: Regex::new(r"(?m)[^\w\s]").unwrap(),
+            trim: false,
+        }
+    }
+}
+
+impl Tokenizer for SimpleTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let text = self.cleanup.replace_all(text, "");
+        text.split_whitespace()
+            .map(|word| match self.trim {
+                true => word.trim_matches(|c| matches!(c, '\'' | '’' | '-')),
+                false => word,
+            })
+            .filter(|word| !word.is_empty())
+            .map(|word| word.to_string())
+            .collect()
+    }
+}
+
+#[doc = "The ApostropheTokenizer struct is the same as"]
+#[doc = "[SimpleTokenizer::aggressive] but keeps straight apostrophes, so"]
+#[doc = "\"Don't!\" becomes \"Don't\". Unlike the default [SimpleTokenizer] it"]
+#[doc = "drops hyphens and doesn't trim apostrophes off the ends of words, so"]
+#[doc = "\"well-known\" becomes \"wellknown\" and \"'n'\" stays as it is."]
+#[derive(Clone, Debug)]
+pub struct ApostropheTokenizer {
+    cleanup: Regex,
+}
+
+impl Default for ApostropheTokenizer {
+    fn default() -> ApostropheTokenizer {
+        ApostropheTokenizer {
+            cleanup: Regex::new(r"(?m)[^\w\s']").unwrap(),
+        }
+    }
+}
+
+impl Tokenizer for ApostropheTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let text = self.cleanup.replace_all(text, "");
+        text.split_whitespace()
+            .map(|word| word.to_string())
+            .collect()
+    }
+}
+
+#[doc = "The PunctuationTokenizer struct splits the text into words (which keep"]
+#[doc = "their inner apostrophes) and punctuation, with every punctuation"]
+#[doc = "character being its own token. \"Don't stop, ok?\" becomes \"Don't\","]
+#[doc = "\"stop\", \",\", \"ok\", \"?\"."]
+#[derive(Clone, Debug)]
+pub struct PunctuationTokenizer {
+    token: Regex,
+    marks: Option<Vec<char>>, // the punctuation kept as tokens, None keeps all of it
+}
+
+impl Default for PunctuationTokenizer {
+    fn default() -> PunctuationTokenizer {
+        PunctuationTokenizer {
+            token: Regex::new(r"\w+(?:'\w+)*|[^\w\s]").unwrap(),
+            marks: None,
+        }
+    }
+}
+
+impl PunctuationTokenizer {
+    #[doc = "The sentence function creates a [PunctuationTokenizer] that only"]
+    #[doc = "keeps . , ! and ? as tokens, dropping any other punctuation."]
+    pub fn sentence() -> PunctuationTokenizer {
+        PunctuationTokenizer {
+            marks: Some(vec!['.', ',', '!', '?']),
+            ..PunctuationTokenizer::default()
+        }
+    }
+}
+
+impl Tokenizer for PunctuationTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        self.token
+            .find_iter(text)
+            .map(|token| token.as_str())
+            .filter(|token| match (&self.marks, is_punctuation(token)) {
+                (Some(marks), true) => token.chars().all(|c| marks.contains(&c)),
+                _ => true,
+            })
+            .map(|token| token.to_string())
+            .collect()
+    }
+}
+
+#[doc = "The UnicodeTokenizer struct splits text on Unicode word boundaries"]
+#[doc = "(UAX #29) instead of whitespace, so text in scripts without spaces"]
+#[doc = "(like Japanese or Chinese) is split into words too, accented words and"]
+#[doc = "combining characters stay whole, and every emoji and punctuation mark"]
+#[doc = "is its own token. Only available with the unicode-segmentation feature."]
+#[cfg(feature = "unicode-segmentation")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UnicodeTokenizer;
+
+#[cfg(feature = "unicode-segmentation")]
+impl Tokenizer for UnicodeTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        use unicode_segmentation::UnicodeSegmentation;
+        text.split_word_bounds()
+            .filter(|segment| !segment.chars().all(char::is_whitespace))
+            .map(|segment| segment.to_string())
+            .collect()
+    }
+}
+
+#[doc = "The is_punctuation function returns if the token is made only of"]
+#[doc = "punctuation (no letters, digits, underscores or whitespace)."]
+pub(crate) fn is_punctuation(token: &str) -> bool {
+    !token.is_empty()
+        && token
+            .chars()
+            .all(|c| !(c.is_alphanumeric() || c == '_' || c.is_whitespace()))
+}
+
+#[doc = "The split_sentences function splits text after every run of . ! or ?"]
+#[doc = "that is followed by whitespace (or the end of the text), keeping the"]
+#[doc = "punctuation with its sentence. \"Hi... you?! ok\" becomes \"Hi...\","]
+#[doc = "\"you?!\" and \"ok\", blank sentences are left out. Abbreviations like"]
+#[doc = "\"Dr.\" end a sentence too."]
+pub fn split_sentences(text: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        if !matches!(c, '.' | '!' | '?') {
+            continue;
+        }
+        while let Some((_, '.' | '!' | '?')) = chars.peek() {
+            chars.next();
+        }
+        match chars.peek() {
+            Some((end, next)) if next.is_whitespace() => {
+                out.push(&text[start..*end]);
+                start = *end;
+            }
+            None => {
+                out.push(&text[start..]);
+                start = text.len();
+            }
+            _ => {}
+        }
+    }
+    out.push(&text[start..]);
+    out.into_iter()
+        .map(str::trim)
+        .filter(|sentence| !sentence.is_empty())
+        .collect()
+}
+
+#[doc = "The join_words function joins generated words into text with spaces"]
+#[doc = "between them, except before punctuation tokens, which are attached to"]
+#[doc = "the word before them: \"hi\", \",\", \"you\", \".\" becomes \"hi, you.\"."]
+pub fn join_words<S: AsRef<str>>(words: &[S]) -> String {
+    let mut out = String::new();
+    for word in words {
+        let word = word.as_ref();
+        if !out.is_empty() && !is_punctuation(word) {
+            out.push(' ');
+        }
+        out.push_str(word);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cleanup_pattern_can_be_changed() {
+        let tokenizer = SimpleTokenizer::with_pattern(r"[0-9]").unwrap();
+        assert_eq!(tokenizer.tokenize("R2-D2, hi"), ["R-D,", "hi"]);
+        assert!(SimpleTokenizer::with_pattern(r"[unclosed").is_err());
+    }
+
+    #[test]
+    fn punctuation_is_attached_when_joining() {
+        let words = PunctuationTokenizer::sentence().tokenize("Well, hi (there) you!");
+        assert_eq!(words, ["Well", ",", "hi", "there", "you", "!"]);
+        assert_eq!(join_words(&words), "Well, hi there you!");
+    }
+
+    #[test]
+    fn sentences_split_after_their_punctuation() {
+        assert_eq!(
+            split_sentences("Hi... you?! 3.14 is pi. ok"),
+            ["Hi...", "you?!", "3.14 is pi.", "ok"]
+        );
+        assert_eq!(split_sentences("... ?! "), ["...", "?!"]);
+        assert!(split_sentences("  ").is_empty());
+        assert_eq!(split_sentences("café. über"), ["café.", "über"]);
+    }
+
+    #[test]
+    fn tokenizers_split_differently() {
+        let text = "Don't stop, OK?";
+        assert_eq!(
+            SimpleTokenizer::default().tokenize(text),
+            ["Don't", "stop", "OK"]
+        );
+        assert_eq!(
+            SimpleTokenizer::aggressive().tokenize(text),
+            ["Dont", "stop", "OK"]
+        );
+        assert_eq!(
+            ApostropheTokenizer::default().tokenize(text),
+            ["Don't", "stop", "OK"]
+        );
+        assert_eq!(
+            PunctuationTokenizer::default().tokenize(text),
+            ["Don't", "stop", ",", "OK", "?"]
+        );
+        let text = "a well-known 'n' word";
+        assert_eq!(
+            SimpleTokenizer::default().tokenize(text),
+            ["a", "well-known", "n", "word"]
+        );
+        assert_eq!(
+            ApostropheTokenizer::default().tokenize(text),
+            ["a", "wellknown", "'n'", "word"]
+        );
+    }
+
+    #[test]
+    fn contractions_and_hyphens_are_kept() {
+        let tokenizer = SimpleTokenizer::default();
+        let cases: &[(&str, &[&str])] = &[
+            ("rock 'n' roll", &["rock", "n", "roll"]),
+            ("o'clock", &["o'clock"]),
+            ("well-known", &["well-known"]),
+            ("--", &[]),
+            ("it's", &["it's"]),
+            ("wait -- what?", &["wait", "what"]),
+            (
+                "'quoted' state-of-the-art-",
+                &["quoted", "state-of-the-art"],
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(tokenizer.tokenize(text), *expected, "{text:?}");
+        }
+        assert_eq!(
+            SimpleTokenizer::aggressive().tokenize("rock 'n' roll, well-known"),
+            ["rock", "n", "roll", "wellknown"]
+        );
+    }
+
+    #[cfg(feature = "unicode-segmentation")]
+    #[test]
+    fn unicode_text_is_split_on_word_boundaries() {
+        let text = "Le café était très bon 👍 東京に行きました. naïve coöperation";
+        let words = UnicodeTokenizer.tokenize(text);
+        assert!(words.contains(&"café".to_string()), "{words:?}");
+        assert!(words.contains(&"naïve".to_string()), "{words:?}");
+        assert!(words.contains(&"👍".to_string()), "{words:?}");
+        assert!(words.contains(&"東".to_string()), "{words:?}");
+        assert!(words.iter().all(|word| !word.trim().is_empty()));
+
+        let mut chain = crate::types::create_markov_chain_with_order(2).unwrap();
+        chain.set_tokenizer(UnicodeTokenizer);
+        chain.train_text(text.to_string());
+        assert!(chain.find_node("le café").is_some());
+        assert!(chain.generate_text(50).is_ok());
+    }
+}
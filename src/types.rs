@@ -1,39 +1,129 @@
+use std::borrow::{Borrow, Cow};
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::BufRead;
+#[cfg(not(feature = "wasm"))]
+use std::io::BufReader;
+use std::ops::ControlFlow;
 use std::ops::{Index, IndexMut};
+#[cfg(not(feature = "wasm"))]
+use std::path::Path;
+#[cfg(all(feature = "rayon", not(feature = "wasm")))]
+use std::path::PathBuf;
+use std::sync::Arc;
 
-use crate::util::compare_const_strs;
-use regex::Regex;
+use crate::error::{MapError, MarkovError};
+use crate::generate::{END_TOKEN, START_TOKEN};
+use crate::probability::SmoothingStrategy;
+use crate::tokenizer::{
+    is_punctuation, join_words, split_sentences, PunctuationTokenizer, SimpleTokenizer, Tokenizer,
+};
+use rand::Rng;
 
-type MarkovNodeID = usize;
+pub type MarkovNodeID = usize;
+
+#[doc = "The DEFAULT_MAX_CHARS constant is how many characters"]
+#[doc = "[MarkovChain::generate_chars] generates at most."]
+pub const DEFAULT_MAX_CHARS: usize = 100;
+
+#[doc = "The MAX_PENDING_BYTES constant is how many bytes of a sentence that"]
+#[doc = "hasn't ended yet [MarkovChain::train_from_reader] holds on to before"]
+#[doc = "training it as it is, so text without end punctuation can't fill up"]
+#[doc = "memory."]
+pub const MAX_PENDING_BYTES: usize = 1 << 16;
 
 #[derive(Clone, Debug)]
 pub struct Map<K: PartialEq + Clone, V: Clone> {
     keys: Vec<K>,
     values: Vec<V>,
-    iterator_idx: usize,
 }
 
+#[doc = "The map_create function creates an empty [Map]."]
+#[deprecated(note = "use Map::new instead")]
 pub fn map_create<K: PartialEq + Clone, V: Clone>() -> Map<K, V> {
+    Map::new()
+}
+
+#[doc = "The map_with_capacity function creates an empty [Map] with room for"]
+#[doc = "capacity keys before it needs to allocate again."]
+pub fn map_with_capacity<K: PartialEq + Clone, V: Clone>(capacity: usize) -> Map<K, V> {
     Map {
-        keys: [].to_vec(),
-        values: [].to_vec(),
-        iterator_idx: 0,
+        keys: Vec::with_capacity(capacity),
+        values: Vec::with_capacity(capacity),
     }
 }
 
+#[doc = "The map_from function is the same as [Map::from_pairs]."]
 pub fn map_from<K: PartialEq + Clone, V: Clone>(from: &[(K, V)]) -> Map<K, V> {
-    let mut out = map_create();
-    for (key, value) in from {
-        out.insert(key.clone(), value.clone());
+    Map::from_pairs(from)
+}
+
+impl<K: PartialEq + Clone, V: Clone> Default for Map<K, V> {
+    fn default() -> Map<K, V> {
+        Map::new()
     }
-    out
 }
 
 impl<K: PartialEq + Clone, V: Clone> Map<K, V> {
+    #[doc = "The new function creates an empty [Map]."]
+    #[doc = ""]
+    #[doc = "```"]
+    #[doc = "use markov_chain_rs::types::Map;"]
+    #[doc = ""]
+    #[doc = "let mut map: Map<&str, u64> = Map::new();"]
+    #[doc = "map.insert(\"cat\", 3);"]
+    #[doc = "assert_eq!(map.get(\"cat\"), Some(3));"]
+    #[doc = "```"]
+    pub fn new() -> Map<K, V> {
+        Map {
+            keys: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+    #[doc = "The from_pairs function creates a [Map] holding the provided keys"]
+    #[doc = "and values, in order."]
+    #[doc = ""]
+    #[doc = "```"]
+    #[doc = "use markov_chain_rs::types::Map;"]
+    #[doc = ""]
+    #[doc = "let map = Map::from_pairs(&[(\"the\", 2), (\"cat\", 1)]);"]
+    #[doc = "assert_eq!(map.into_keys(), [\"the\", \"cat\"]);"]
+    #[doc = "```"]
+    pub fn from_pairs(pairs: &[(K, V)]) -> Map<K, V> {
+        let mut out = Map::with_capacity(pairs.len());
+        out.batch_insert(pairs);
+        out
+    }
+    #[doc = "The with_capacity function is the same as [map_with_capacity]."]
+    #[doc = ""]
+    #[doc = "```"]
+    #[doc = "use markov_chain_rs::types::Map;"]
+    #[doc = ""]
+    #[doc = "let map: Map<String, u64> = Map::with_capacity(1000);"]
+    #[doc = "assert!(map.is_empty());"]
+    #[doc = "```"]
+    pub fn with_capacity(capacity: usize) -> Map<K, V> {
+        map_with_capacity(capacity)
+    }
     #[doc = "The insert function adds the key to the [Map] with the provided value."]
     pub fn insert(&mut self, key: K, value: V) {
         self.keys.push(key);
         self.values.push(value);
     }
+    #[doc = "The batch_insert function adds every key in items to the [Map] with"]
+    #[doc = "its value, in order, like calling [Map::insert] for each of them but"]
+    #[doc = "growing the map only once, for loading many entries at a time."]
+    pub fn batch_insert(&mut self, items: &[(K, V)]) {
+        self.keys.reserve(items.len());
+        self.values.reserve(items.len());
+        for (key, value) in items {
+            self.keys.push(key.clone());
+            self.values.push(value.clone());
+        }
+    }
     #[doc = "The set function changes the keys value to the provided value"]
     #[doc = "and returns if it found the key or not (true=key found, false=key"]
     #[doc = "not found)"]
@@ -55,6 +145,24 @@ impl<K: PartialEq + Clone, V: Clone> Map<K, V> {
             self.insert(key, value);
         }
     }
+    #[doc = "The get_or_insert function returns a mutable reference to the value"]
+    #[doc = "of the key, inserting the key with the provided default first if it"]
+    #[doc = "isn't in the [Map], like [std::collections::HashMap]'s entry API."]
+    pub fn get_or_insert(&mut self, key: K, default: V) -> &mut V {
+        self.get_or_insert_with(key, || default)
+    }
+    #[doc = "The get_or_insert_with function is the same as [Map::get_or_insert]"]
+    #[doc = "but only calls f to make the default if the key isn't there."]
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> &mut V {
+        let idx = match self.position(key.clone()) {
+            Some(idx) => idx,
+            None => {
+                self.insert(key, f());
+                self.keys.len() - 1
+            }
+        };
+        &mut self.values[idx]
+    }
     #[doc = "The get function simply returns the value in the [Map]"]
     #[doc = "if it found the key, and [None] if it didn't."]
     pub fn get(&self, key: K) -> Option<V> {
@@ -65,21 +173,57 @@ impl<K: PartialEq + Clone, V: Clone> Map<K, V> {
         }
         None
     }
-    fn get_or_panic(&self, key: K) -> &V {
-        for i in 0..self.keys.len() {
-            if self.keys[i] == key {
-                return &self.values[i];
-            }
-        }
-        panic!("cannot find key in [Map]")
+    #[doc = "The try_get function returns a reference to the value of the key,"]
+    #[doc = "or [None] if the key isn't in the [Map]. Unlike [Map::get] the value"]
+    #[doc = "isn't cloned, and unlike indexing it never panics."]
+    pub fn try_get(&self, key: &K) -> Option<&V> {
+        let idx = self.keys.iter().position(|k| k == key)?;
+        Some(&self.values[idx])
     }
-    fn get_idx(&self, key: K) -> Option<usize> {
-        for i in 0..self.keys.len() {
-            if self.keys[i] == key {
-                return Some(i);
-            }
+    #[doc = "The get_mut function returns a mutable reference to the value of"]
+    #[doc = "the key, or [None] if the key isn't in the [Map]."]
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let idx = self.keys.iter().position(|k| k == key)?;
+        Some(&mut self.values[idx])
+    }
+    #[doc = "The position function returns the index of the key in the [Map]"]
+    #[doc = "(its insertion order), or [None] if the key isn't in it."]
+    pub fn position(&self, key: K) -> Option<usize> {
+        self.keys.iter().position(|k| *k == key)
+    }
+    #[doc = "The contains_value function returns if any key of the [Map] has the"]
+    #[doc = "provided value."]
+    pub fn contains_value(&self, value: &V) -> bool
+    where
+        V: PartialEq,
+    {
+        self.values.contains(value)
+    }
+    #[doc = "The len function returns the number of keys in the [Map]."]
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+    #[doc = "The is_empty function returns if the [Map] has no keys."]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+    #[doc = "The iter function returns an iterator over references to the"]
+    #[doc = "keys and values of the [Map], in insertion order."]
+    pub fn iter(&self) -> std::iter::Zip<std::slice::Iter<'_, K>, std::slice::Iter<'_, V>> {
+        self.keys.iter().zip(self.values.iter())
+    }
+    #[doc = "The map_values function returns a new [Map] with the same keys and"]
+    #[doc = "each value replaced by the result of calling f on it."]
+    pub fn map_values<U: Clone, F: FnMut(V) -> U>(&self, f: F) -> Map<K, U> {
+        Map {
+            keys: self.keys.clone(),
+            values: self.values.iter().cloned().map(f).collect(),
         }
-        None
+    }
+    #[doc = "The map_values_inplace function calls f on every value of the [Map],"]
+    #[doc = "letting it change the value in place."]
+    pub fn map_values_inplace<F: FnMut(&mut V)>(&mut self, f: F) {
+        self.values.iter_mut().for_each(f);
     }
     #[doc = "The has function returns if it found the provided key in the [Map]."]
     pub fn has(&self, key: K) -> bool {
@@ -90,107 +234,3211 @@ impl<K: PartialEq + Clone, V: Clone> Map<K, V> {
         }
         false
     }
+    #[doc = "The remove function takes the key out of the [Map] and returns"]
+    #[doc = "its value, or [None] if the key wasn't in the [Map]. The order of"]
+    #[doc = "the other keys is kept."]
+    pub fn remove(&mut self, key: K) -> Option<V> {
+        let idx = self.position(key)?;
+        self.keys.remove(idx);
+        Some(self.values.remove(idx))
+    }
+    #[doc = "The swap function exchanges the values of the two keys in place,"]
+    #[doc = "without cloning them. The keys keep their order. If either key isn't"]
+    #[doc = "in the [Map] nothing changes and [MapError::KeyNotFound] is returned."]
+    pub fn swap(&mut self, key1: K, key2: K) -> Result<(), MapError> {
+        let idx1 = self.position(key1).ok_or(MapError::KeyNotFound)?;
+        let idx2 = self.position(key2).ok_or(MapError::KeyNotFound)?;
+        self.values.swap(idx1, idx2);
+        Ok(())
+    }
+    #[doc = "The pop function removes the last inserted key from the [Map] and"]
+    #[doc = "returns it with its value, or [None] if the [Map] is empty."]
+    pub fn pop(&mut self) -> Option<(K, V)> {
+        let key = self.keys.pop()?;
+        let value = self.values.pop()?;
+        Some((key, value))
+    }
+    #[doc = "The first function returns the first inserted key and its value"]
+    #[doc = "without removing them, or [None] if the [Map] is empty."]
+    pub fn first(&self) -> Option<(&K, &V)> {
+        Some((self.keys.first()?, self.values.first()?))
+    }
+    #[doc = "The last function returns the last inserted key and its value"]
+    #[doc = "without removing them, or [None] if the [Map] is empty."]
+    pub fn last(&self) -> Option<(&K, &V)> {
+        Some((self.keys.last()?, self.values.last()?))
+    }
+    #[doc = "The dedup function merges every key that was inserted more than"]
+    #[doc = "once (with [Map::insert] instead of [Map::add]) into a single entry,"]
+    #[doc = "at the position of its first one, combining the values in order with"]
+    #[doc = "merge_fn (like |a, b| a + b for portions)."]
+    pub fn dedup<F: FnMut(V, V) -> V>(&mut self, mut merge_fn: F) {
+        let keys = std::mem::take(&mut self.keys);
+        let values = std::mem::take(&mut self.values);
+        for (key, value) in keys.into_iter().zip(values) {
+            match self.position(key.clone()) {
+                Some(idx) => {
+                    let first = self.values[idx].clone();
+                    self.values[idx] = merge_fn(first, value);
+                }
+                None => self.insert(key, value),
+            }
+        }
+    }
+    #[doc = "The shrink_to_fit function frees the memory the [Map] holds on to"]
+    #[doc = "beyond what its keys and values need."]
+    pub fn shrink_to_fit(&mut self) {
+        self.keys.shrink_to_fit();
+        self.values.shrink_to_fit();
+    }
+    #[doc = "The heap_bytes function returns how many bytes the [Map] has"]
+    #[doc = "allocated for its keys and values (not counting anything they point"]
+    #[doc = "to themselves)."]
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.keys.capacity() * std::mem::size_of::<K>()
+            + self.values.capacity() * std::mem::size_of::<V>()
+    }
+    #[doc = "The sort_by function sorts the keys (and their values) of the [Map]"]
+    #[doc = "with the provided comparison, keeping the order of keys it finds"]
+    #[doc = "equal."]
+    pub fn sort_by<F: FnMut((&K, &V), (&K, &V)) -> Ordering>(&mut self, mut cmp: F) {
+        let keys = std::mem::take(&mut self.keys);
+        let values = std::mem::take(&mut self.values);
+        let mut pairs: Vec<(K, V)> = keys.into_iter().zip(values).collect();
+        pairs.sort_by(|a, b| cmp((&a.0, &a.1), (&b.0, &b.1)));
+        (self.keys, self.values) = pairs.into_iter().unzip();
+    }
+    #[doc = "The sort_by_key function sorts the [Map] by its keys."]
+    pub fn sort_by_key(&mut self)
+    where
+        K: Ord,
+    {
+        self.sort_by(|a, b| a.0.cmp(b.0));
+    }
+    #[doc = "The sort_by_value function sorts the [Map] by its values, keeping"]
+    #[doc = "the order of keys with equal values."]
+    pub fn sort_by_value(&mut self)
+    where
+        V: Ord,
+    {
+        self.sort_by(|a, b| a.1.cmp(b.1));
+    }
+    #[doc = "The truncate function keeps only the first n keys (and their values)"]
+    #[doc = "of the [Map], doing nothing if it has n keys or fewer. Sorting first"]
+    #[doc = "(see [Map::sort_by]) keeps the top n by any order."]
+    pub fn truncate(&mut self, n: usize) {
+        self.keys.truncate(n);
+        self.values.truncate(n);
+    }
+    #[doc = "The into_vec function consumes the [Map] and returns its keys and"]
+    #[doc = "values as pairs, in insertion order, without cloning them."]
+    pub fn into_vec(self) -> Vec<(K, V)> {
+        self.keys.into_iter().zip(self.values).collect()
+    }
+    #[doc = "The into_keys function consumes the [Map] and returns its keys, in"]
+    #[doc = "insertion order."]
+    pub fn into_keys(self) -> Vec<K> {
+        self.keys
+    }
+    #[doc = "The into_values function consumes the [Map] and returns its values,"]
+    #[doc = "in insertion order."]
+    pub fn into_values(self) -> Vec<V> {
+        self.values
+    }
 }
 
-impl<K: PartialEq + Clone, V: Clone> Iterator for Map<K, V> {
+impl<K: PartialEq + Clone, V: Clone> IntoIterator for Map<K, V> {
     type Item = (K, V);
-    fn next(&mut self) -> Option<Self::Item> {
-        self.iterator_idx += 1;
-        if self.keys.len() <= self.iterator_idx - 1 {
-            return None;
-        }
-        Some((
-            self.keys[self.iterator_idx - 1].clone(),
-            self.values[self.iterator_idx - 1].clone(),
-        ))
+    type IntoIter = std::iter::Zip<std::vec::IntoIter<K>, std::vec::IntoIter<V>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.keys.into_iter().zip(self.values)
+    }
+}
+
+impl<'a, K: PartialEq + Clone, V: Clone> IntoIterator for &'a Map<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = std::iter::Zip<std::slice::Iter<'a, K>, std::slice::Iter<'a, V>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
     }
 }
 
 impl<K: PartialEq + Clone, V: Clone> Index<K> for Map<K, V> {
     type Output = V;
     fn index(&self, index: K) -> &Self::Output {
-        self.get_or_panic(index)
+        let len = self.len();
+        self.try_get(&index)
+            .unwrap_or_else(|| panic!("cannot find key in [Map] of {len} keys"))
     }
 }
 
 impl<K: PartialEq + Clone, V: Clone> IndexMut<K> for Map<K, V> {
     fn index_mut(&mut self, index: K) -> &mut Self::Output {
-        if !self.has(index.clone()) {
-            panic!("cannot find key in [Map]")
-        }
-        let idx = self
-            .get_idx(index)
-            .expect("somehow there is no index found for the provided key");
-        &mut self.values[idx]
+        let len = self.len();
+        self.get_mut(&index)
+            .unwrap_or_else(|| panic!("cannot find key in [Map] of {len} keys"))
     }
 }
 
 #[derive(Clone, Debug)]
-pub struct MarkovNode {
-    pub data: *const str,
+pub struct MarkovNode<T: Eq + Hash + Clone = String> {
+    pub data: T,
     pub id: MarkovNodeID,
     pub links: Map<MarkovNodeID, u64>, // key is a MarkovNodeID, value is the number of "portions"
 }
 
-#[derive(Debug)]
-pub struct MarkovChain {
-    pub root: MarkovNode,
-    pub nodes: Vec<MarkovNode>,                   // excluding the root
-    pub nodes_map: Map<MarkovNodeID, MarkovNode>, // excluding the root
-    all_portions: u64,
-    newest_id: MarkovNodeID,
+#[doc = "The MarkovChain struct is a chain over tokens of type T, which are"]
+#[doc = "words ([String]s) unless another type is given. Text-specific methods"]
+#[doc = "like [MarkovChain::train_text] are only available for [TextChain]s."]
+#[derive(Clone, Debug)]
+pub struct MarkovChain<T: Eq + Hash + Clone = String> {
+    pub root: MarkovNode<T>,
+    pub nodes: Vec<MarkovNode<T>>,               // excluding the root
+    pub nodes_map: HashMap<MarkovNodeID, usize>, // key is a MarkovNodeID, value is the index into nodes
+    pub(crate) all_portions: u64,
+    pub(crate) newest_id: MarkovNodeID,
+    pub(crate) index: HashMap<T, MarkovNodeID>, // key is a node's data, value is its MarkovNodeID
+    smoothing: SmoothingStrategy,
+    order: usize,
+    tokenizer: Arc<dyn Tokenizer>,
+    case_sensitive: bool,
+    stopwords: HashSet<String>, // normalized words dropped from text before training
+    normalizer: Option<Normalizer>,
+    surface_forms: HashMap<String, String>, // key is a normalized word, value is the first form of it trained
+    pub(crate) lower_order: Option<Box<MarkovChain<T>>>, // the same training at order - 1, for backoff
+    split_link: Option<SplitLink<T>>, // set along with lower_order, see SplitLink
+    pub(crate) novelty: Option<NoveltyMemory>, // what was trained, for generating only new sentences
+    pub(crate) total_tokens_trained: u64, // tokens passed to training, times the weight they were trained with
+    pub(crate) continuation_counts: HashMap<MarkovNodeID, u64>, // key is a MarkovNodeID, value is the number of distinct nodes (including the root) linking to it
 }
 
+#[doc = "The SplitLink type is the function a chain with a lower order uses to"]
+#[doc = "turn one of its links into the links of the lower-order chain that"]
+#[doc = "link is made of, as (from, to) data with [None] for the root. Every"]
+#[doc = "change to the links goes through it, so the lower-order chains always"]
+#[doc = "hold what training the same text at their order would."]
+type SplitLink<T> = fn(&MarkovChain<T>, MarkovNodeID, MarkovNodeID) -> Vec<(Option<T>, Option<T>)>;
+
+#[doc = "The Normalizer struct holds the function set with"]
+#[doc = "[MarkovChain::set_normalizer]."]
+#[derive(Clone)]
+pub(crate) struct Normalizer(pub(crate) Arc<dyn Fn(&str) -> String + Send + Sync>);
+
+impl fmt::Debug for Normalizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Normalizer")
+    }
+}
+
+#[doc = "The NoveltyMemory struct holds hashes of every sentence a chain was"]
+#[doc = "trained on and of every run of max_overlap + 1 words in them, so"]
+#[doc = "[MarkovChain::generate_sentence] can tell if it is repeating the"]
+#[doc = "training text. See [MarkovChainBuilder::novelty_check]."]
+#[derive(Clone, Debug)]
+pub(crate) struct NoveltyMemory {
+    pub(crate) max_overlap: usize,
+    sentences: HashSet<u64>,
+    windows: HashSet<u64>,
+}
+
+impl NoveltyMemory {
+    pub(crate) fn new(max_overlap: usize) -> NoveltyMemory {
+        NoveltyMemory {
+            max_overlap,
+            sentences: HashSet::new(),
+            windows: HashSet::new(),
+        }
+    }
+    fn hash_words(words: &[String]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        words.hash(&mut hasher);
+        hasher.finish()
+    }
+    #[doc = "The remember function records a trained sentence, leaving out"]
+    #[doc = "[START_TOKEN] and [END_TOKEN] like generated sentences do."]
+    pub(crate) fn remember(&mut self, words: &[String]) {
+        let words: Vec<String> = words
+            .iter()
+            .filter(|word| *word != START_TOKEN && *word != END_TOKEN)
+            .cloned()
+            .collect();
+        if words.is_empty() {
+            return;
+        }
+        self.sentences.insert(NoveltyMemory::hash_words(&words));
+        for window in words.windows(self.max_overlap + 1) {
+            self.windows.insert(NoveltyMemory::hash_words(window));
+        }
+    }
+    #[doc = "The is_novel function returns if the (normalized) words are not a"]
+    #[doc = "trained sentence and share no more than max_overlap words in a row"]
+    #[doc = "with one."]
+    pub(crate) fn is_novel(&self, words: &[String]) -> bool {
+        !self.sentences.contains(&NoveltyMemory::hash_words(words))
+            && !words
+                .windows(self.max_overlap + 1)
+                .any(|window| self.windows.contains(&NoveltyMemory::hash_words(window)))
+    }
+    fn merge(&mut self, other: &NoveltyMemory) {
+        self.sentences.extend(other.sentences.iter().copied());
+        self.windows.extend(other.windows.iter().copied());
+    }
+    fn shrink_to_fit(&mut self) {
+        self.sentences.shrink_to_fit();
+        self.windows.shrink_to_fit();
+    }
+    fn heap_bytes(&self) -> usize {
+        (self.sentences.capacity() + self.windows.capacity()) * std::mem::size_of::<u64>()
+    }
+}
+
+#[doc = "The TextChain type is a [MarkovChain] over words, which is what"]
+#[doc = "[MarkovChain] means when no token type is given."]
+pub type TextChain = MarkovChain<String>;
+
+#[doc = "The create_markov_chain function creates an empty chain of order 1."]
+#[deprecated(note = "use MarkovChain::new instead")]
 pub fn create_markov_chain() -> MarkovChain {
+    MarkovChain::new()
+}
+
+#[doc = "The create_token_chain function creates an empty chain over tokens of"]
+#[doc = "any type, trained with [MarkovChain::train_token_pair] and"]
+#[doc = "[MarkovChain::train_tokens]. The root's data is T's default value, it"]
+#[doc = "is never compared against trained tokens."]
+pub fn create_token_chain<T: Eq + Hash + Clone + Default>() -> MarkovChain<T> {
     MarkovChain {
         root: MarkovNode {
-            data: "",
+            data: T::default(),
             id: 0,
-            links: map_create(),
+            links: Map::new(),
         },
         nodes: [].to_vec(),
-        nodes_map: map_create(),
+        nodes_map: HashMap::new(),
         all_portions: 0,
         newest_id: 0,
+        index: HashMap::new(),
+        smoothing: SmoothingStrategy::None,
+        order: 1,
+        tokenizer: Arc::new(SimpleTokenizer::default()),
+        case_sensitive: false,
+        stopwords: HashSet::new(),
+        normalizer: None,
+        surface_forms: HashMap::new(),
+        lower_order: None,
+        split_link: None,
+        novelty: None,
+        total_tokens_trained: 0,
+        continuation_counts: HashMap::new(),
+    }
+}
+
+#[doc = "The create_markov_chain_with_order function creates a chain where each"]
+#[doc = "node is the last order words (joined by spaces) instead of a single"]
+#[doc = "word, so generation picks each word based on the order words before it."]
+#[doc = "Text shorter than order words is trained as a single shorter node."]
+#[doc = "[MarkovChain::new] is the same as an order of 1. An order of 0 gives"]
+#[doc = "[MarkovError::InvalidConfig]."]
+pub fn create_markov_chain_with_order(order: usize) -> Result<MarkovChain, MarkovError> {
+    if order == 0 {
+        return Err(MarkovError::InvalidConfig(
+            "a markov chain needs an order of at least 1".to_string(),
+        ));
+    }
+    Ok(chain_with_order(order))
+}
+
+#[doc = "The chain_with_order function is [create_markov_chain_with_order]"]
+#[doc = "once the order is known to be at least 1."]
+fn chain_with_order(order: usize) -> MarkovChain {
+    let mut chain = create_token_chain();
+    chain.order = order;
+    if order > 1 {
+        chain.lower_order = Some(Box::new(chain_with_order(order - 1)));
+        chain.split_link = Some(MarkovChain::split_link);
+    }
+    chain
+}
+
+#[doc = "The TrainStats struct is what [MarkovChain::train_from_reader]"]
+#[doc = "returns about the training it did."]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrainStats {
+    pub lines: usize,     // lines read from the reader
+    pub tokens: usize,    // tokens trained, after stopwords are dropped
+    pub new_nodes: usize, // nodes that didn't exist before
+}
+
+#[doc = "The TrainProgress struct is what"]
+#[doc = "[MarkovChain::train_from_reader_with_progress] reports while training."]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrainProgress {
+    pub bytes: usize,  // bytes read so far
+    pub lines: usize,  // lines read so far
+    pub tokens: usize, // tokens trained so far
+    pub nodes: usize,  // nodes in the chain now
+}
+
+#[doc = "The ChainSummary struct is what [MarkovChain::summary] returns, the"]
+#[doc = "same overview that displaying a [MarkovChain] prints. The word lists"]
+#[doc = "hold at most [SUMMARY_WORDS] entries each."]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChainSummary {
+    pub words: usize,                    // nodes in the chain, not counting the root
+    pub transitions: usize,              // links, including the ones out of the root
+    pub total_portions: u64,             // portions of every link added up
+    pub order: usize,                    // words of context each node holds
+    pub top_words: Vec<(String, usize)>, // words linking to the most distinct words, with that count
+    pub start_words: Vec<(String, u64)>, // words the root links to most, with the portions
+}
+
+#[doc = "The SUMMARY_WORDS constant is how many words [MarkovChain::summary]"]
+#[doc = "lists for the top words and the start words."]
+pub const SUMMARY_WORDS: usize = 10;
+
+#[doc = "The SUMMARY_WORD_CHARS constant is how many characters of a word"]
+#[doc = "displaying a [MarkovChain] shows before cutting it off with an ellipsis."]
+pub const SUMMARY_WORD_CHARS: usize = 20;
+
+#[doc = "The open_file function opens the file at path for training, telling"]
+#[doc = "missing files and files that can't be read apart."]
+#[cfg(not(feature = "wasm"))]
+fn open_file(path: &Path) -> Result<BufReader<std::fs::File>, MarkovError> {
+    match std::fs::File::open(path) {
+        Ok(file) => Ok(BufReader::new(file)),
+        Err(err) => Err(match err.kind() {
+            std::io::ErrorKind::NotFound => MarkovError::FileNotFound(path.to_path_buf()),
+            std::io::ErrorKind::PermissionDenied => {
+                MarkovError::PermissionDenied(path.to_path_buf())
+            }
+            _ => err.into(),
+        }),
+    }
+}
+
+#[doc = "The MarkovChainBuilder struct configures a [MarkovChain] before it is"]
+#[doc = "created. [MarkovChainBuilder::new] starts from the same configuration"]
+#[doc = "as [MarkovChain::new]."]
+#[derive(Clone, Debug, Default)]
+pub struct MarkovChainBuilder {
+    smoothing: SmoothingStrategy,
+    case_sensitive: bool,
+    vocabulary: usize,
+    order: Option<usize>, // None is an order of 1
+    keep_punctuation: bool,
+    tokenizer: Option<Arc<dyn Tokenizer>>,
+    stopwords: Vec<String>,
+    novelty_check: Option<usize>, // None doesn't remember the training text
+}
+
+impl MarkovChainBuilder {
+    pub fn new() -> MarkovChainBuilder {
+        MarkovChainBuilder::default()
+    }
+    #[doc = "The smoothing function sets the [SmoothingStrategy] used for"]
+    #[doc = "transitions that were never trained."]
+    pub fn smoothing(mut self, smoothing: SmoothingStrategy) -> MarkovChainBuilder {
+        self.smoothing = smoothing;
+        self
+    }
+    #[doc = "The case_sensitive function sets if the chain keeps the case of the"]
+    #[doc = "words it is trained on, see [MarkovChain::set_case_sensitive]."]
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> MarkovChainBuilder {
+        self.case_sensitive = case_sensitive;
+        self
+    }
+    #[doc = "The vocabulary function sets how many words the chain is expected to"]
+    #[doc = "be trained on, so room for that many nodes is allocated up front."]
+    #[doc = "It is only an estimate, the chain still grows past it."]
+    pub fn vocabulary(mut self, vocabulary: usize) -> MarkovChainBuilder {
+        self.vocabulary = vocabulary;
+        self
+    }
+    #[doc = "The order function sets how many words make up each node, see"]
+    #[doc = "[create_markov_chain_with_order]. It must be at least 1."]
+    pub fn order(mut self, order: usize) -> MarkovChainBuilder {
+        self.order = Some(order);
+        self
+    }
+    #[doc = "The keep_punctuation function sets if . , ! and ? are trained as"]
+    #[doc = "their own tokens, see [MarkovChain::set_keep_punctuation]. It can't"]
+    #[doc = "be combined with [MarkovChainBuilder::tokenizer]."]
+    pub fn keep_punctuation(mut self, keep: bool) -> MarkovChainBuilder {
+        self.keep_punctuation = keep;
+        self
+    }
+    #[doc = "The tokenizer function sets the [Tokenizer] the chain splits text"]
+    #[doc = "with, see [MarkovChain::set_tokenizer]."]
+    pub fn tokenizer(mut self, tokenizer: impl Tokenizer + 'static) -> MarkovChainBuilder {
+        self.tokenizer = Some(Arc::new(tokenizer));
+        self
+    }
+    #[doc = "The stopwords function sets the words text training skips, see"]
+    #[doc = "[MarkovChain::set_stopwords]."]
+    pub fn stopwords(mut self, words: impl IntoIterator<Item = String>) -> MarkovChainBuilder {
+        self.stopwords = words.into_iter().collect();
+        self
+    }
+    #[doc = "The novelty_check function makes the chain remember (as hashes)"]
+    #[doc = "every sentence it is trained on, so [MarkovChain::generate_sentence]"]
+    #[doc = "tries again instead of returning a trained sentence or anything"]
+    #[doc = "sharing more than max_overlap words in a row with one. It costs"]
+    #[doc = "memory for every trained sentence, so it is off unless set here."]
+    #[doc = "max_overlap must be at least 1."]
+    pub fn novelty_check(mut self, max_overlap: usize) -> MarkovChainBuilder {
+        self.novelty_check = Some(max_overlap);
+        self
+    }
+    #[doc = "The build function creates the configured [MarkovChain], or returns"]
+    #[doc = "[MarkovError::InvalidConfig] if the configuration makes no sense"]
+    #[doc = "(like a negative Laplace k, an order of 0, or keeping punctuation"]
+    #[doc = "with a custom tokenizer, which decides that itself)."]
+    pub fn build(self) -> Result<MarkovChain, MarkovError> {
+        self.smoothing.validate()?;
+        let order = self.order.unwrap_or(1);
+        if self.keep_punctuation && self.tokenizer.is_some() {
+            return Err(MarkovError::InvalidConfig(
+                "keep_punctuation can't be combined with a custom tokenizer".to_string(),
+            ));
+        }
+        if self.novelty_check == Some(0) {
+            return Err(MarkovError::InvalidConfig(
+                "novelty_check needs a max_overlap of at least 1".to_string(),
+            ));
+        }
+        let mut chain = create_markov_chain_with_order(order)?;
+        chain.novelty = self.novelty_check.map(NoveltyMemory::new);
+        chain.smoothing = self.smoothing;
+        chain.case_sensitive = self.case_sensitive;
+        chain.set_keep_punctuation(self.keep_punctuation);
+        if let Some(tokenizer) = self.tokenizer {
+            chain.tokenizer = tokenizer;
+        }
+        chain.set_stopwords(self.stopwords);
+        chain.nodes = Vec::with_capacity(self.vocabulary);
+        chain.nodes_map = HashMap::with_capacity(self.vocabulary);
+        chain.index = HashMap::with_capacity(self.vocabulary);
+        Ok(chain)
+    }
+}
+
+#[doc = "The default_rng function returns the rng used by the functions that"]
+#[doc = "don't take one: [rand::thread_rng], or [rand::rngs::OsRng] with the"]
+#[doc = "wasm feature."]
+#[cfg(not(feature = "wasm"))]
+pub(crate) fn default_rng() -> rand::rngs::ThreadRng {
+    rand::thread_rng()
+}
+
+#[cfg(feature = "wasm")]
+pub(crate) fn default_rng() -> rand::rngs::OsRng {
+    rand::rngs::OsRng
+}
+
+#[doc = "The sample_link function picks one of the provided links at random,"]
+#[doc = "with each link being as likely as its share of the portions. It"]
+#[doc = "returns [None] without touching the rng if there is nothing to pick."]
+fn sample_link<R: Rng + ?Sized>(
+    links: &Map<MarkovNodeID, u64>,
+    rng: &mut R,
+) -> Option<MarkovNodeID> {
+    sample_weighted(links.iter().map(|(id, portions)| (*id, *portions)), rng)
+}
+
+pub(crate) fn sample_weighted<I, R>(choices: I, rng: &mut R) -> Option<MarkovNodeID>
+where
+    I: Iterator<Item = (MarkovNodeID, u64)> + Clone,
+    R: Rng + ?Sized,
+{
+    let total: u64 = choices.clone().map(|(_, portions)| portions).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut pick = rng.gen_range(0..total);
+    for (id, portions) in choices {
+        if pick < portions {
+            return Some(id);
+        }
+        pick -= portions;
     }
+    None
 }
 
-impl MarkovChain {
+impl<T: Eq + Hash + Clone> MarkovChain<T> {
+    #[doc = "The node function returns the node with the provided id, including"]
+    #[doc = "the root (id 0), or [None] if there is no such node."]
+    pub fn node(&self, id: MarkovNodeID) -> Option<&MarkovNode<T>> {
+        if id == self.root.id {
+            return Some(&self.root);
+        }
+        self.nodes_map.get(&id).map(|idx| &self.nodes[*idx])
+    }
+    fn node_mut(&mut self, id: MarkovNodeID) -> Option<&mut MarkovNode<T>> {
+        if id == self.root.id {
+            return Some(&mut self.root);
+        }
+        match self.nodes_map.get(&id) {
+            Some(idx) => Some(&mut self.nodes[*idx]),
+            None => None,
+        }
+    }
+    #[doc = "The all_portions function returns the number of portions across"]
+    #[doc = "every link in the chain, including the links out of the root."]
+    pub fn all_portions(&self) -> u64 {
+        self.all_portions
+    }
+    #[doc = "The order function returns how many words make up each node of a"]
+    #[doc = "[TextChain]. Chains made with [create_token_chain] have an order of 1."]
+    pub fn order(&self) -> usize {
+        self.order
+    }
+    #[doc = "The smoothing function returns the [SmoothingStrategy] of the chain."]
+    pub fn smoothing(&self) -> SmoothingStrategy {
+        self.smoothing
+    }
+    #[doc = "The set_smoothing function changes the [SmoothingStrategy] of the"]
+    #[doc = "chain, returning [MarkovError::InvalidConfig] (and keeping the old"]
+    #[doc = "strategy) if it makes no sense."]
+    pub fn set_smoothing(&mut self, smoothing: SmoothingStrategy) -> Result<(), MarkovError> {
+        smoothing.validate()?;
+        self.smoothing = smoothing;
+        Ok(())
+    }
+    #[doc = "The find_token function returns the node holding exactly the"]
+    #[doc = "provided token, or [None] if it was never trained. The root is never"]
+    #[doc = "returned. [TextChain]s should usually use [MarkovChain::find_node],"]
+    #[doc = "which applies the chain's case sensitivity to the word first."]
+    pub fn find_token<Q>(&self, word: &Q) -> Option<&MarkovNode<T>>
+    where
+        T: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.index.get(word).and_then(|id| self.node(*id))
+    }
+    pub(crate) fn find_or_create_node<Q>(&mut self, word: &Q) -> MarkovNodeID
+    where
+        T: Borrow<Q>,
+        Q: Eq + Hash + ToOwned<Owned = T> + ?Sized,
+    {
+        if let Some(node) = self.find_token(word) {
+            return node.id;
+        }
+        self.newest_id += 1;
+        self.nodes.push(MarkovNode {
+            data: word.to_owned(),
+            id: self.newest_id,
+            links: Map::new(),
+        });
+        self.nodes_map.insert(self.newest_id, self.nodes.len() - 1);
+        self.index.insert(word.to_owned(), self.newest_id);
+        self.newest_id
+    }
+    pub(crate) fn add_portions(&mut self, from: MarkovNodeID, to: MarkovNodeID, portions: u64) {
+        let node = self
+            .node_mut(from)
+            .expect("somehow there is no node for the provided id");
+        let current = node.links.get_or_insert(to, 0);
+        let new_link = *current == 0;
+        *current += portions;
+        if new_link {
+            *self.continuation_counts.entry(to).or_insert(0) += 1;
+        }
+        self.all_portions += portions;
+    }
+    #[doc = "The remove_portions function takes up to the provided portions off"]
+    #[doc = "the link between the two nodes, removing the link once it has none"]
+    #[doc = "left, and returns how many portions were actually taken off."]
+    pub(crate) fn remove_portions(
+        &mut self,
+        from: MarkovNodeID,
+        to: MarkovNodeID,
+        portions: u64,
+    ) -> u64 {
+        let node = match self.node_mut(from) {
+            Some(node) => node,
+            None => return 0,
+        };
+        let current = match node.links.get_mut(&to) {
+            Some(current) => current,
+            None => return 0,
+        };
+        let removed = (*current).min(portions);
+        *current -= removed;
+        if *current == 0 {
+            node.links.remove(to);
+            let count = self.continuation_counts.entry(to).or_insert(1);
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                self.continuation_counts.remove(&to);
+            }
+        }
+        self.all_portions -= removed;
+        removed
+    }
+    #[doc = "The add_transition function adds portions to the link like"]
+    #[doc = "add_portions, and to the links it is made of in the lower-order"]
+    #[doc = "chains, for every change but the training in train_sequence, which"]
+    #[doc = "trains the lower-order chains itself."]
+    pub(crate) fn add_transition(&mut self, from: MarkovNodeID, to: MarkovNodeID, portions: u64) {
+        self.add_portions(from, to, portions);
+        self.add_to_lower_order(from, to, portions);
+    }
+    #[doc = "The remove_transition function takes portions off the link like"]
+    #[doc = "remove_portions, and the same number off the links it is made of in"]
+    #[doc = "the lower-order chains."]
+    pub(crate) fn remove_transition(
+        &mut self,
+        from: MarkovNodeID,
+        to: MarkovNodeID,
+        portions: u64,
+    ) -> u64 {
+        let lower_links = self.lower_order_links(from, to);
+        let removed = self.remove_portions(from, to, portions);
+        let lower = match &mut self.lower_order {
+            Some(lower) if removed > 0 => lower,
+            _ => return removed,
+        };
+        for (lower_from, lower_to) in lower_links {
+            let lower_id = |data: Option<T>| match data {
+                Some(data) => lower.find_token(&data).map(|node| node.id),
+                None => Some(lower.root.id),
+            };
+            if let (Some(lower_from), Some(lower_to)) = (lower_id(lower_from), lower_id(lower_to)) {
+                lower.remove_transition(lower_from, lower_to, removed);
+            }
+        }
+        removed
+    }
+    #[doc = "The lower_order_links function returns the links of the lower-order"]
+    #[doc = "chain the link from one node to the other is made of (see"]
+    #[doc = "[SplitLink]), or none if the chain has no lower order."]
+    fn lower_order_links(
+        &self,
+        from: MarkovNodeID,
+        to: MarkovNodeID,
+    ) -> Vec<(Option<T>, Option<T>)> {
+        match self.split_link {
+            Some(split_link) => split_link(self, from, to),
+            None => Vec::new(),
+        }
+    }
+    #[doc = "The add_to_lower_order function adds portions to the links of the"]
+    #[doc = "lower-order chain the link from one node to the other is made of,"]
+    #[doc = "creating their nodes if needed."]
+    fn add_to_lower_order(&mut self, from: MarkovNodeID, to: MarkovNodeID, portions: u64) {
+        let lower_links = self.lower_order_links(from, to);
+        let lower = match &mut self.lower_order {
+            Some(lower) => lower,
+            None => return,
+        };
+        for (lower_from, lower_to) in lower_links {
+            let mut lower_id = |data: Option<T>| match data {
+                Some(data) => lower.find_or_create_node(&data),
+                None => lower.root.id,
+            };
+            let lower_from = lower_id(lower_from);
+            let lower_to = lower_id(lower_to);
+            lower.add_transition(lower_from, lower_to, portions);
+        }
+    }
+    #[doc = "The rebuild_lower_order function rebuilds the lower-order chains"]
+    #[doc = "from the links of this one, for after changes that can't be carried"]
+    #[doc = "over link by link, like removing or renaming nodes."]
+    pub(crate) fn rebuild_lower_order(&mut self) {
+        let lower = match &self.lower_order {
+            Some(lower) => lower.empty_like(),
+            None => return,
+        };
+        self.lower_order = Some(Box::new(lower));
+        let links: Vec<(MarkovNodeID, MarkovNodeID, u64)> = std::iter::once(&self.root)
+            .chain(self.nodes.iter())
+            .flat_map(|node| node.links.iter().map(|(to, p)| (node.id, *to, *p)))
+            .collect();
+        for (from, to, portions) in links {
+            self.add_to_lower_order(from, to, portions);
+        }
+    }
+    #[doc = "The remove_nodes function removes the nodes with the provided ids"]
+    #[doc = "along with every link into or out of them, keeping all_portions,"]
+    #[doc = "nodes_map, the word index and the lower-order chains in sync. The"]
+    #[doc = "root can't be removed."]
+    pub(crate) fn remove_nodes(&mut self, ids: &[MarkovNodeID]) {
+        if ids.is_empty() {
+            return;
+        }
+        let mut removed_portions = 0;
+        for node in std::iter::once(&mut self.root).chain(self.nodes.iter_mut()) {
+            if ids.contains(&node.id) {
+                removed_portions += node.links.iter().map(|(_, p)| *p).sum::<u64>();
+                continue;
+            }
+            for id in ids {
+                removed_portions += node.links.remove(*id).unwrap_or(0);
+            }
+        }
+        self.all_portions -= removed_portions;
+        self.nodes.retain(|node| !ids.contains(&node.id));
+        self.rebuild_lookups();
+        self.rebuild_lower_order();
+    }
+    #[doc = "The merge_nodes function moves every link into or out of the node"]
+    #[doc = "with id from onto the node with id into (adding up portions where"]
+    #[doc = "both had a link) and then removes the from node."]
+    pub(crate) fn merge_nodes(&mut self, from: MarkovNodeID, into: MarkovNodeID) {
+        let incoming = self.predecessor_ids(from);
+        let outgoing: Vec<(MarkovNodeID, u64)> = self
+            .node(from)
+            .map(|node| node.links.iter().map(|(id, p)| (*id, *p)).collect())
+            .unwrap_or_default();
+        self.remove_nodes(&[from]);
+        for (id, portions) in incoming {
+            if id != from {
+                self.add_transition(id, into, portions);
+            }
+        }
+        for (id, portions) in outgoing {
+            let to = if id == from { into } else { id };
+            self.add_transition(into, to, portions);
+        }
+    }
+    #[doc = "The rebuild_lookups function rebuilds nodes_map, the word index and"]
+    #[doc = "continuation_counts from nodes, for after nodes have been moved or"]
+    #[doc = "removed."]
+    pub(crate) fn rebuild_lookups(&mut self) {
+        self.nodes_map = HashMap::with_capacity(self.nodes.len());
+        self.index = HashMap::with_capacity(self.nodes.len());
+        for (idx, node) in self.nodes.iter().enumerate() {
+            self.nodes_map.insert(node.id, idx);
+            self.index.insert(node.data.clone(), node.id);
+        }
+        self.continuation_counts = HashMap::new();
+        for node in std::iter::once(&self.root).chain(self.nodes.iter()) {
+            for (to, _) in node.links.iter() {
+                *self.continuation_counts.entry(*to).or_insert(0) += 1;
+            }
+        }
+    }
+    #[doc = "The total_tokens_trained function returns how many tokens the chain"]
+    #[doc = "has been trained on, counting every word of trained text (after"]
+    #[doc = "stopwords are dropped) and both words of every trained pair, times"]
+    #[doc = "the weight they were trained with. Unlike all_portions it doesn't"]
+    #[doc = "count the links into and out of the root."]
+    pub fn total_tokens_trained(&self) -> u64 {
+        self.total_tokens_trained
+    }
+    #[doc = "The continuation_count function returns how many distinct nodes"]
+    #[doc = "(including the root) link to the node with the provided id, the"]
+    #[doc = "number of contexts it has been trained after."]
+    pub fn continuation_count(&self, id: MarkovNodeID) -> u64 {
+        self.continuation_counts.get(&id).copied().unwrap_or(0)
+    }
+    #[doc = "The reset_portions function sets every link (including the links"]
+    #[doc = "out of the root) to weight portions, keeping which words link to"]
+    #[doc = "which but forgetting how often, so the chain can be retrained on a"]
+    #[doc = "new corpus with only these transitions known. A weight of 0 is"]
+    #[doc = "ignored, since it would leave links that can never be picked."]
+    pub fn reset_portions(&mut self, weight: u64) {
+        if weight == 0 {
+            return;
+        }
+        let mut links = 0;
+        for node in std::iter::once(&mut self.root).chain(self.nodes.iter_mut()) {
+            node.links.map_values_inplace(|portions| *portions = weight);
+            links += node.links.len() as u64;
+        }
+        self.all_portions = links * weight;
+        if let Some(lower) = &mut self.lower_order {
+            lower.reset_portions(weight);
+        }
+    }
+    #[doc = "The shrink_to_fit function frees the memory the chain holds on to"]
+    #[doc = "beyond what its nodes and links need, for after a chain is done"]
+    #[doc = "training (or has had nodes removed) and is only used to generate."]
+    pub fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+        self.nodes_map.shrink_to_fit();
+        self.continuation_counts.shrink_to_fit();
+        self.index.shrink_to_fit();
+        self.surface_forms.shrink_to_fit();
+        if let Some(novelty) = &mut self.novelty {
+            novelty.shrink_to_fit();
+        }
+        for node in std::iter::once(&mut self.root).chain(self.nodes.iter_mut()) {
+            node.links.shrink_to_fit();
+        }
+        if let Some(lower) = &mut self.lower_order {
+            lower.shrink_to_fit();
+        }
+    }
+    #[doc = "The compact function renumbers the nodes 1, 2, 3 and so on in their"]
+    #[doc = "current order, updating every link, so the ids left unused by"]
+    #[doc = "removed nodes are reclaimed and newest_id is the number of nodes"]
+    #[doc = "again. Ids handed out before compacting may point at other nodes"]
+    #[doc = "afterwards, look words up again instead of keeping ids around."]
+    pub fn compact(&mut self) {
+        let ids: HashMap<MarkovNodeID, MarkovNodeID> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(idx, node)| (node.id, idx + 1))
+            .chain(std::iter::once((self.root.id, self.root.id)))
+            .collect();
+        for node in std::iter::once(&mut self.root).chain(self.nodes.iter_mut()) {
+            node.id = ids[&node.id];
+            let mut links = map_with_capacity(node.links.len());
+            for (to, portions) in node.links.iter() {
+                match ids.get(to) {
+                    Some(to) => links.insert(*to, *portions),
+                    None => self.all_portions -= portions,
+                }
+            }
+            node.links = links;
+        }
+        self.newest_id = self.nodes.len();
+        self.rebuild_lookups();
+        debug_assert!(
+            std::iter::once(&self.root)
+                .chain(self.nodes.iter())
+                .all(|node| node.links.iter().all(|(to, _)| self.node(*to).is_some())),
+            "compacting left a link to a node that doesn't exist"
+        );
+        if let Some(lower) = &mut self.lower_order {
+            lower.compact();
+        }
+    }
+    #[doc = "The empty_like function returns an empty chain with the same"]
+    #[doc = "configuration (order, smoothing, tokenizer) as this one."]
+    pub(crate) fn empty_like(&self) -> MarkovChain<T> {
+        MarkovChain {
+            root: MarkovNode {
+                data: self.root.data.clone(),
+                id: self.root.id,
+                links: Map::new(),
+            },
+            nodes: [].to_vec(),
+            nodes_map: HashMap::new(),
+            all_portions: 0,
+            newest_id: 0,
+            index: HashMap::new(),
+            smoothing: self.smoothing,
+            order: self.order,
+            tokenizer: self.tokenizer.clone(),
+            case_sensitive: self.case_sensitive,
+            stopwords: self.stopwords.clone(),
+            normalizer: self.normalizer.clone(),
+            surface_forms: self.surface_forms.clone(),
+            lower_order: self
+                .lower_order
+                .as_ref()
+                .map(|lower| Box::new(lower.empty_like())),
+            split_link: self.split_link,
+            novelty: self
+                .novelty
+                .as_ref()
+                .map(|novelty| NoveltyMemory::new(novelty.max_overlap)),
+            total_tokens_trained: 0,
+            continuation_counts: HashMap::new(),
+        }
+    }
+    #[doc = "The matching_id function returns the id of the node in this chain"]
+    #[doc = "with the same data as the provided node of other (the root matches"]
+    #[doc = "the root), or [None] if there isn't one."]
+    fn matching_id(&self, other: &MarkovChain<T>, node: &MarkovNode<T>) -> Option<MarkovNodeID> {
+        if node.id == other.root.id {
+            return Some(self.root.id);
+        }
+        self.find_token(&node.data).map(|node| node.id)
+    }
+    #[doc = "The intersection function returns a new chain with only the links"]
+    #[doc = "(matched by word) that are in both chains, each with the smaller of"]
+    #[doc = "the two portions. Words left without any links are not included. The"]
+    #[doc = "new chain has this chain's configuration."]
+    pub fn intersection(&self, other: &MarkovChain<T>) -> MarkovChain<T> {
+        let mut out = self.empty_like();
+        for node in std::iter::once(&self.root).chain(self.nodes.iter()) {
+            let other_from = match other.matching_id(self, node).and_then(|id| other.node(id)) {
+                Some(other_from) => other_from,
+                None => continue,
+            };
+            for (to, portions) in node.links.iter() {
+                let to = match self.node(*to) {
+                    Some(to) => to,
+                    None => continue,
+                };
+                let shared = match other.matching_id(self, to) {
+                    Some(other_to) => other_from.links.get(other_to).unwrap_or(0).min(*portions),
+                    None => 0,
+                };
+                if shared == 0 {
+                    continue;
+                }
+                let from = if node.id == self.root.id {
+                    out.root.id
+                } else {
+                    out.find_or_create_node(&node.data)
+                };
+                let to = out.find_or_create_node(&to.data);
+                out.add_transition(from, to, shared);
+            }
+        }
+        out
+    }
+    #[doc = "The subtract function takes the portions of every link in other off"]
+    #[doc = "the matching link (by word) in this chain, stopping at zero instead of"]
+    #[doc = "going negative. Links left with no portions are removed, the words"]
+    #[doc = "themselves are kept."]
+    pub fn subtract(&mut self, other: &MarkovChain<T>) {
+        for other_node in std::iter::once(&other.root).chain(other.nodes.iter()) {
+            let from = match self.matching_id(other, other_node) {
+                Some(from) => from,
+                None => continue,
+            };
+            for (other_to, portions) in other_node.links.iter() {
+                let to = match other
+                    .node(*other_to)
+                    .and_then(|node| self.matching_id(other, node))
+                {
+                    Some(to) => to,
+                    None => continue,
+                };
+                self.remove_transition(from, to, *portions);
+            }
+        }
+    }
+    #[doc = "The merge function adds every link of other to the matching link"]
+    #[doc = "(by word) in this chain, creating the words this chain doesn't have"]
+    #[doc = "in the order other created them. Merging chains trained on parts of"]
+    #[doc = "a corpus gives the same chain as training on all of it in order."]
+    pub fn merge(&mut self, other: &MarkovChain<T>) {
+        for node in other.nodes.iter() {
+            self.find_or_create_node(&node.data);
+        }
+        for other_node in std::iter::once(&other.root).chain(other.nodes.iter()) {
+            let from = match self.matching_id(other, other_node) {
+                Some(from) => from,
+                None => continue,
+            };
+            for (other_to, portions) in other_node.links.iter() {
+                let to = match other
+                    .node(*other_to)
+                    .and_then(|node| self.matching_id(other, node))
+                {
+                    Some(to) => to,
+                    None => continue,
+                };
+                self.add_portions(from, to, *portions);
+            }
+        }
+        for (word, surface) in other.surface_forms.iter() {
+            self.surface_forms
+                .entry(word.clone())
+                .or_insert_with(|| surface.clone());
+        }
+        self.total_tokens_trained += other.total_tokens_trained;
+        if let (Some(novelty), Some(other_novelty)) = (&mut self.novelty, &other.novelty) {
+            novelty.merge(other_novelty);
+        }
+        if let (Some(lower), Some(other_lower)) = (&mut self.lower_order, &other.lower_order) {
+            lower.merge(other_lower);
+        }
+    }
+    #[doc = "The train_token_pair function adds a portion to the link from one"]
+    #[doc = "token to the other, creating either token's node if needed."]
+    pub fn train_token_pair(&mut self, from: T, to: T) {
+        let from = self.find_or_create_node(&from);
+        let to = self.find_or_create_node(&to);
+        self.add_transition(from, to, 1);
+        self.total_tokens_trained += 2;
+    }
+    #[doc = "The train_tokens function links the root to the first token and each"]
+    #[doc = "token to the next, exactly as given (the order of a [TextChain] is not"]
+    #[doc = "applied, see [MarkovChain::train_text] for that)."]
+    pub fn train_tokens(&mut self, tokens: &[T]) {
+        let mut previous = self.root.id;
+        for token in tokens {
+            let id = self.find_or_create_node(token);
+            self.add_transition(previous, id, 1);
+            previous = id;
+        }
+        self.total_tokens_trained += tokens.len() as u64;
+    }
+    #[doc = "The step function picks a random link out of the node (weighted by"]
+    #[doc = "portions) and returns the node it leads to, or [None] if the node"]
+    #[doc = "has no links or the link picked leads back to the root (the end)."]
+    pub(crate) fn step<R: Rng + ?Sized>(
+        &self,
+        node: &MarkovNode<T>,
+        rng: &mut R,
+    ) -> Option<&MarkovNode<T>> {
+        let next = sample_link(&node.links, rng)?;
+        if next == self.root.id {
+            return None;
+        }
+        Some(
+            self.node(next)
+                .expect("somehow a link points to a node that doesn't exist"),
+        )
+    }
+    #[doc = "The generate_tokens_with_rng function walks the chain from the root"]
+    #[doc = "with the provided rng, returning the token of every node it reaches"]
+    #[doc = "until it reaches a node with no links, follows a link back to the"]
+    #[doc = "root, or has max_tokens tokens."]
+    pub fn generate_tokens_with_rng<R: Rng>(
+        &self,
+        rng: &mut R,
+        max_tokens: usize,
+    ) -> Result<Vec<T>, MarkovError> {
+        if self.root.links.is_empty() {
+            return Err(MarkovError::EmptyChain);
+        }
+        let mut out = Vec::new();
+        let mut current = &self.root;
+        while out.len() < max_tokens {
+            current = match self.step(current, rng) {
+                Some(next) => next,
+                None => break,
+            };
+            out.push(current.data.clone());
+        }
+        Ok(out)
+    }
+}
+
+impl Default for MarkovChain {
+    fn default() -> MarkovChain {
+        MarkovChain::new()
+    }
+}
+
+impl MarkovChain<String> {
+    #[doc = "The new function creates an empty chain of order 1, see"]
+    #[doc = "[create_markov_chain_with_order] for other orders and"]
+    #[doc = "[MarkovChainBuilder] for other configurations."]
+    #[doc = ""]
+    #[doc = "```"]
+    #[doc = "use markov_chain_rs::types::MarkovChain;"]
+    #[doc = ""]
+    #[doc = "let mut chain = MarkovChain::new();"]
+    #[doc = "chain.train_text(\"the cat sat\".to_string());"]
+    #[doc = "assert_eq!(chain.probability(\"the\", \"cat\"), 1.0);"]
+    #[doc = "```"]
+    pub fn new() -> MarkovChain {
+        chain_with_order(1)
+    }
+    #[doc = "The set_tokenizer function changes the [Tokenizer] that"]
+    #[doc = "[MarkovChain::train_text] (and everything else that takes text) uses."]
+    #[doc = "The default is [SimpleTokenizer]."]
+    pub fn set_tokenizer(&mut self, tokenizer: impl Tokenizer + 'static) {
+        self.tokenizer = Arc::new(tokenizer);
+    }
+    #[doc = "The set_cleanup_pattern function makes the chain use a"]
+    #[doc = "[SimpleTokenizer] that removes whatever the provided regex matches"]
+    #[doc = "(compiled once, here), see [SimpleTokenizer::with_pattern]. An invalid"]
+    #[doc = "pattern is returned as [MarkovError::Parse] and leaves the"]
+    #[doc = "tokenizer unchanged."]
+    pub fn set_cleanup_pattern(&mut self, pattern: &str) -> Result<(), MarkovError> {
+        let tokenizer = SimpleTokenizer::with_pattern(pattern)
+            .map_err(|err| MarkovError::Parse(err.to_string()))?;
+        self.set_tokenizer(tokenizer);
+        Ok(())
+    }
+    #[doc = "The tokenize function splits the text into the words train_text"]
+    #[doc = "would train, using the chain's [Tokenizer]."]
+    pub(crate) fn tokenize(&self, text: &str) -> Vec<String> {
+        self.clean_tokens(self.tokenizer.tokenize(text))
+    }
+    #[doc = "The set_case_sensitive function sets if the chain keeps the case of"]
+    #[doc = "words. By default it doesn't: everything trained is lowercased, and"]
+    #[doc = "so are the words looked up with [MarkovChain::find_node], so \"Paris\""]
+    #[doc = "and \"paris\" are the same word. Case-sensitive chains keep them"]
+    #[doc = "apart. Words that were already trained are left as they are."]
+    pub fn set_case_sensitive(&mut self, case_sensitive: bool) {
+        self.case_sensitive = case_sensitive;
+    }
+    pub fn case_sensitive(&self) -> bool {
+        self.case_sensitive
+    }
+    #[doc = "The fold_case function returns the word unchanged if the chain is"]
+    #[doc = "case-sensitive, lowercased otherwise."]
+    fn fold_case<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        if self.case_sensitive {
+            Cow::Borrowed(word)
+        } else {
+            Cow::Owned(word.to_lowercase())
+        }
+    }
+    #[doc = "The normalize function returns the word (or space-separated words)"]
+    #[doc = "the way this chain stores it: case folded unless the chain is"]
+    #[doc = "case-sensitive, then run through the normalizer if there is one."]
+    pub(crate) fn normalize<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        let word = self.fold_case(word);
+        match &self.normalizer {
+            Some(normalizer) => Cow::Owned(
+                word.split(' ')
+                    .map(|word| (normalizer.0)(word))
+                    .collect::<Vec<String>>()
+                    .join(" "),
+            ),
+            None => word,
+        }
+    }
+    #[doc = "The surface_tokens function case folds the words coming out of a"]
+    #[doc = "[Tokenizer] and drops the stopwords."]
+    fn surface_tokens(&self, words: Vec<String>) -> Vec<String> {
+        words
+            .into_iter()
+            .map(|word| self.fold_case(&word).into_owned())
+            .filter(|word| !self.stopwords.contains(word))
+            .collect()
+    }
+    #[doc = "The clean_tokens function turns the words coming out of a [Tokenizer]"]
+    #[doc = "into the words that get trained (or looked up)."]
+    fn clean_tokens(&self, words: Vec<String>) -> Vec<String> {
+        let words = self.surface_tokens(words);
+        match &self.normalizer {
+            Some(normalizer) => words.iter().map(|word| (normalizer.0)(word)).collect(),
+            None => words,
+        }
+    }
+    #[doc = "The training_tokens function is the same as clean_tokens, but also"]
+    #[doc = "remembers the first surface form of every normalized word."]
+    fn training_tokens(&mut self, words: Vec<String>) -> Vec<String> {
+        let words = self.surface_tokens(words);
+        let normalizer = match &self.normalizer {
+            Some(normalizer) => normalizer.0.clone(),
+            None => return words,
+        };
+        words
+            .into_iter()
+            .map(|word| {
+                let normalized = normalizer(&word);
+                self.surface_forms.entry(normalized.clone()).or_insert(word);
+                normalized
+            })
+            .collect()
+    }
+    #[doc = "The set_normalizer function makes the chain run every word through"]
+    #[doc = "f (after case folding and stopwords) before training it or looking"]
+    #[doc = "it up, so a stemmer can make \"running\" and \"runs\" the same node."]
+    #[doc = "Generation emits the first surface form trained for each normalized"]
+    #[doc = "word (\"running\" if that came first), not the normalized word."]
+    #[doc = "Words that were already trained are left as they are."]
+    pub fn set_normalizer(&mut self, f: impl Fn(&str) -> String + Send + Sync + 'static) {
+        self.normalizer = Some(Normalizer(Arc::new(f)));
+    }
+    #[doc = "The clear_normalizer function stops running words through the"]
+    #[doc = "normalizer set with [MarkovChain::set_normalizer]."]
+    pub fn clear_normalizer(&mut self) {
+        self.normalizer = None;
+    }
+    #[doc = "The surface_form function returns the word generation emits for the"]
+    #[doc = "normalized word: the first form of it that was trained, or the word"]
+    #[doc = "itself without a normalizer."]
+    pub fn surface_form<'a>(&'a self, word: &'a str) -> &'a str {
+        self.surface_forms
+            .get(word)
+            .map(|surface| surface.as_str())
+            .unwrap_or(word)
+    }
+    #[doc = "The surface_words function returns the words generation emits for"]
+    #[doc = "the node, every word of it run through [MarkovChain::surface_form]."]
+    pub(crate) fn surface_words<'a>(
+        &'a self,
+        node: &'a MarkovNode,
+    ) -> impl Iterator<Item = String> + 'a {
+        node.data
+            .split(' ')
+            .map(|word| self.surface_form(word).to_string())
+    }
+    #[doc = "The set_stopwords function makes text training skip the provided"]
+    #[doc = "words entirely, replacing any stopwords set before. The words"]
+    #[doc = "around a stopword are linked to each other, so \"the cat sat\" with"]
+    #[doc = "\"cat\" stopped trains the->sat. Stopwords follow the chain's case"]
+    #[doc = "sensitivity, and words that were already trained are kept."]
+    pub fn set_stopwords(&mut self, words: impl IntoIterator<Item = String>) {
+        self.stopwords = words
+            .into_iter()
+            .map(|word| self.fold_case(&word).into_owned())
+            .collect();
+    }
+    #[doc = "The clear_stopwords function removes every stopword, so all words"]
+    #[doc = "are trained again."]
+    pub fn clear_stopwords(&mut self) {
+        self.stopwords.clear();
+    }
+    #[doc = "The is_stopword function returns if text training skips the word."]
+    pub fn is_stopword(&self, word: &str) -> bool {
+        self.stopwords.contains(self.fold_case(word).as_ref())
+    }
+    #[doc = "The memory_usage_bytes function estimates how many bytes the chain"]
+    #[doc = "has allocated on the heap: its nodes, their words and links, the"]
+    #[doc = "lookups and the lower-order chains used by"]
+    #[doc = "[MarkovChain::backoff_generate]. Hash maps are counted by capacity,"]
+    #[doc = "so the real number is a bit higher."]
+    pub fn memory_usage_bytes(&self) -> usize {
+        let node_bytes = |node: &MarkovNode| node.data.capacity() + node.links.heap_bytes();
+        let index_entry = std::mem::size_of::<(String, MarkovNodeID)>();
+        self.nodes.capacity() * std::mem::size_of::<MarkovNode>()
+            + node_bytes(&self.root)
+            + self.nodes.iter().map(node_bytes).sum::<usize>()
+            + self.nodes_map.capacity() * std::mem::size_of::<(MarkovNodeID, usize)>()
+            + self.continuation_counts.capacity() * std::mem::size_of::<(MarkovNodeID, u64)>()
+            + self.index.capacity() * index_entry
+            + self.index.keys().map(|word| word.capacity()).sum::<usize>()
+            + self.novelty.as_ref().map_or(0, NoveltyMemory::heap_bytes)
+            + self.lower_order.as_ref().map_or(0, |lower| {
+                lower.memory_usage_bytes() + std::mem::size_of::<MarkovChain>()
+            })
+    }
+    #[doc = "The find_node function returns the node holding the provided word (or"]
+    #[doc = "words, for higher-order chains), or [None] if it was never trained."]
+    #[doc = "The word is lowercased first unless the chain is case-sensitive."]
+    pub fn find_node(&self, word: &str) -> Option<&MarkovNode> {
+        self.find_token(self.normalize(word).as_ref())
+    }
+    #[doc = "The find_nodes_ignoring_case function returns every node whose word"]
+    #[doc = "matches the provided one ignoring case, for searching a"]
+    #[doc = "case-sensitive chain (\"paris\" finds both \"Paris\" and \"paris\")."]
+    #[doc = "Unlike [MarkovChain::find_node] it looks at every node, so it is slow"]
+    #[doc = "on big chains."]
+    pub fn find_nodes_ignoring_case(&self, word: &str) -> Vec<&MarkovNode> {
+        let word = word.to_lowercase();
+        self.nodes
+            .iter()
+            .filter(|node| node.data.to_lowercase() == word)
+            .collect()
+    }
+    #[doc = "The contexts function turns the words into the nodes a chain of"]
+    #[doc = "this order trains them as: every run of order words, joined by"]
+    #[doc = "spaces. Too few words for a full run become a single shorter node."]
+    pub(crate) fn contexts(&self, words: &[String]) -> Vec<String> {
+        if words.is_empty() {
+            return Vec::new();
+        }
+        if words.len() < self.order {
+            return vec![words.join(" ")];
+        }
+        words.windows(self.order).map(|run| run.join(" ")).collect()
+    }
     pub fn train_text(&mut self, text: String) {
-        let mut text = text.clone();
-        text = text.to_lowercase();
-        let regex = Regex::new(r"(?m)[^\w\s]").unwrap();
-        text = regex.replace_all(&text, "").to_string();
-    }
-    pub fn train_word(&mut self, from_word: *const str, to_word: *const str) {
-        let mut i = 0;
-        for val in self.nodes.clone() {
-            if compare_const_strs(val.data, from_word) {
-                for (val2, portions) in val.links.clone() {
-                    if compare_const_strs(self.nodes_map[val2].data, to_word) {
-                        self.nodes[i].links[val2] = portions + 1;
-                        self.all_portions += 1;
-                        return;
-                    }
+        self.train_text_weighted(&text, 1);
+    }
+    #[doc = "The train_text_weighted function trains the text like"]
+    #[doc = "[MarkovChain::train_text], but adds weight portions to every link"]
+    #[doc = "instead of one, the same as training the text weight times. A weight"]
+    #[doc = "of 0 trains nothing."]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(bytes = text.len(), weight))
+    )]
+    pub fn train_text_weighted(&mut self, text: &str, weight: u64) {
+        if weight == 0 {
+            return;
+        }
+        #[cfg(feature = "tracing")]
+        let start = std::time::Instant::now();
+        let tokenizer = self.tokenizer.clone();
+        let _tokens = self.train_sentences(text, tokenizer.as_ref(), weight);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(tokens = _tokens, elapsed = ?start.elapsed(), "trained text");
+    }
+    #[doc = "The train_lines function trains every line of the text like"]
+    #[doc = "[MarkovChain::train_text], so each line is its own sequence from the"]
+    #[doc = "root to the terminal, for corpora like chat logs with one utterance"]
+    #[doc = "per line. Blank lines and lines with no words are skipped."]
+    pub fn train_lines(&mut self, text: &str) {
+        let tokenizer = self.tokenizer.clone();
+        for line in text.lines() {
+            self.train_sentences(line, tokenizer.as_ref(), 1);
+        }
+    }
+    #[doc = "The train_from_sentences function trains every provided sentence"]
+    #[doc = "as its own sequence, from the root to the terminal, tokenized and"]
+    #[doc = "cleaned the same way as [MarkovChain::train_text]. Unlike"]
+    #[doc = "[MarkovChain::train_text] the sentences aren't split any further, so"]
+    #[doc = "each one is trained whole even if it has no end punctuation or has"]
+    #[doc = "some in the middle. Sentences with no words are skipped."]
+    pub fn train_from_sentences<I, S>(&mut self, sentences: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let tokenizer = self.tokenizer.clone();
+        for sentence in sentences {
+            let words = self.training_tokens(tokenizer.tokenize(sentence.as_ref()));
+            self.train_sentence(&words, 1);
+        }
+    }
+    #[doc = "The train_sentence_pairs function trains both sentences of every"]
+    #[doc = "pair like [MarkovChain::train_text], then also links each node of"]
+    #[doc = "the first sentence to the node at the same position in the second"]
+    #[doc = "(stopping at the end of the shorter one), for teaching the chain"]
+    #[doc = "which words go together across two kinds of text, like a sentence"]
+    #[doc = "and its translation or a message and its reply."]
+    pub fn train_sentence_pairs<I>(&mut self, pairs: I)
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let tokenizer = self.tokenizer.clone();
+        for (first, second) in pairs {
+            // each sentence is tokenized once, for training it and for the pairing
+            let mut sides = [Vec::new(), Vec::new()];
+            for (text, words) in [first, second].iter().zip(&mut sides) {
+                for sentence in split_sentences(text) {
+                    let tokens = self.training_tokens(tokenizer.tokenize(sentence));
+                    self.train_sentence(&tokens, 1);
+                    words.extend(tokens);
                 }
-                let mut i2 = 0;
-                for val in self.nodes.clone() {
-                    if compare_const_strs(val.data, to_word) {
-                        self.nodes[i].links.add(i2, 1);
-                        return;
-                    }
-                    i2 += 1;
+            }
+            let first = self.contexts(&sides[0]);
+            let second = self.contexts(&sides[1]);
+            for (from, to) in first.iter().zip(second.iter()) {
+                let from = self.find_or_create_node(from);
+                let to = self.find_or_create_node(to);
+                self.add_transition(from, to, 1);
+            }
+        }
+    }
+    #[doc = "The train_sentences function splits the text into sentences with"]
+    #[doc = "[split_sentences] and trains each one as its own sequence, from the"]
+    #[doc = "root to the terminal, so no link crosses a sentence boundary."]
+    #[doc = "Sentences with no words (like \"...\") are skipped."]
+    #[doc = "It returns how many tokens were trained."]
+    fn train_sentences(&mut self, text: &str, tokenizer: &dyn Tokenizer, weight: u64) -> usize {
+        let mut tokens = 0;
+        for sentence in split_sentences(text) {
+            let words = self.training_tokens(tokenizer.tokenize(sentence));
+            tokens += self.train_sentence(&words, weight);
+        }
+        tokens
+    }
+    #[doc = "The train_sentence function trains the words of one sentence as its"]
+    #[doc = "own sequence, from the root to the terminal, and returns how many"]
+    #[doc = "tokens were trained. Sentences with no words are skipped."]
+    fn train_sentence(&mut self, words: &[String], weight: u64) -> usize {
+        if words.iter().all(|word| is_punctuation(word)) {
+            return 0;
+        }
+        self.train_sequence(words, weight, true);
+        words.len()
+    }
+    #[doc = "The train_from_reader function trains text read from the reader the"]
+    #[doc = "same way [MarkovChain::train_text] trains it all at once, but reads"]
+    #[doc = "it a line at a time, only holding on to the sentence that hasn't"]
+    #[doc = "ended yet, so huge corpora don't need to fit in memory. Unlike"]
+    #[doc = "[MarkovChain::train_text], a blank line also ends a sentence, and so"]
+    #[doc = "does reaching [MAX_PENDING_BYTES] without end punctuation. Errors from"]
+    #[doc = "the reader (including text that isn't UTF-8) are returned as"]
+    #[doc = "[MarkovError::Io], keeping whatever was trained before them."]
+    pub fn train_from_reader<R: BufRead>(&mut self, reader: R) -> Result<TrainStats, MarkovError> {
+        self.train_from_lines(reader, false, 1, 0, None)
+    }
+    #[doc = "The train_from_reader_weighted function trains like"]
+    #[doc = "[MarkovChain::train_from_reader], but adds weight portions to every"]
+    #[doc = "link instead of one, so a small corpus can count as much as a big"]
+    #[doc = "one. A weight of 0 trains nothing and doesn't read the reader."]
+    pub fn train_from_reader_weighted<R: BufRead>(
+        &mut self,
+        reader: R,
+        weight: u64,
+    ) -> Result<TrainStats, MarkovError> {
+        if weight == 0 {
+            return Ok(TrainStats::default());
+        }
+        self.train_from_lines(reader, false, weight, 0, None)
+    }
+    #[doc = "The train_from_file function opens the file at path and trains it"]
+    #[doc = "with [MarkovChain::train_from_reader]. A missing file gives"]
+    #[doc = "[MarkovError::FileNotFound] and a file that can't be read gives"]
+    #[doc = "[MarkovError::PermissionDenied]. Use"]
+    #[doc = "[MarkovChain::train_from_file_lossy] for files that aren't clean UTF-8."]
+    #[doc = "Not available with the wasm feature, which has no filesystem."]
+    #[cfg(not(feature = "wasm"))]
+    pub fn train_from_file(&mut self, path: impl AsRef<Path>) -> Result<TrainStats, MarkovError> {
+        let reader = open_file(path.as_ref())?;
+        self.train_from_lines(reader, false, 1, 0, None)
+    }
+    #[doc = "The train_from_file_lossy function is the same as"]
+    #[doc = "[MarkovChain::train_from_file], but bytes that aren't valid UTF-8 are"]
+    #[doc = "replaced with U+FFFD instead of stopping the training. Not"]
+    #[doc = "available with the wasm feature."]
+    #[cfg(not(feature = "wasm"))]
+    pub fn train_from_file_lossy(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<TrainStats, MarkovError> {
+        let reader = open_file(path.as_ref())?;
+        self.train_from_lines(reader, true, 1, 0, None)
+    }
+    #[doc = "The train_files_parallel function trains every file like"]
+    #[doc = "[MarkovChain::train_from_file], each into its own chain on the rayon"]
+    #[doc = "thread pool, then merges them in with [MarkovChain::merge] in the"]
+    #[doc = "order of paths, so the result is the same as training the files one"]
+    #[doc = "after another. If any file fails nothing is trained and the error of"]
+    #[doc = "the first failing path is returned. Only available with the rayon"]
+    #[doc = "feature, and not with the wasm feature."]
+    #[cfg(all(feature = "rayon", not(feature = "wasm")))]
+    pub fn train_files_parallel(&mut self, paths: &[PathBuf]) -> Result<TrainStats, MarkovError> {
+        use rayon::prelude::*;
+        let parts: Vec<(MarkovChain, TrainStats)> = paths
+            .par_iter()
+            .map(|path| {
+                let mut part = self.empty_like();
+                let stats = part.train_from_file(path)?;
+                Ok((part, stats))
+            })
+            .collect::<Result<_, MarkovError>>()?;
+        let nodes = self.nodes.len();
+        let mut stats = TrainStats::default();
+        for (part, part_stats) in parts {
+            self.merge(&part);
+            stats.lines += part_stats.lines;
+            stats.tokens += part_stats.tokens;
+        }
+        stats.new_nodes = self.nodes.len() - nodes;
+        Ok(stats)
+    }
+    #[doc = "The train_from_reader_with_progress function trains like"]
+    #[doc = "[MarkovChain::train_from_reader], calling progress with a"]
+    #[doc = "[TrainProgress] every time another every_tokens tokens (at least 1)"]
+    #[doc = "have been trained. If progress returns [ControlFlow::Break] training"]
+    #[doc = "stops there, keeping every sentence trained so far, and the stats up"]
+    #[doc = "to that point are returned."]
+    pub fn train_from_reader_with_progress<R, F>(
+        &mut self,
+        reader: R,
+        every_tokens: usize,
+        mut progress: F,
+    ) -> Result<TrainStats, MarkovError>
+    where
+        R: BufRead,
+        F: FnMut(TrainProgress) -> ControlFlow<()>,
+    {
+        self.train_from_lines(reader, false, 1, every_tokens.max(1), Some(&mut progress))
+    }
+    #[doc = "The train_from_lines function does the training of"]
+    #[doc = "[MarkovChain::train_from_reader], replacing invalid UTF-8 if lossy is"]
+    #[doc = "set, adding weight portions to every link and reporting progress"]
+    #[doc = "every so many tokens if asked to."]
+    fn train_from_lines<R: BufRead>(
+        &mut self,
+        mut reader: R,
+        lossy: bool,
+        weight: u64,
+        every: usize,
+        mut progress: Option<&mut dyn FnMut(TrainProgress) -> ControlFlow<()>>,
+    ) -> Result<TrainStats, MarkovError> {
+        let tokenizer = self.tokenizer.clone();
+        let nodes = self.nodes.len();
+        let mut stats = TrainStats::default();
+        let mut bytes = 0;
+        let mut reported = 0;
+        let mut pending = String::new();
+        let mut scanned = 0; // bytes of pending already searched for a sentence end
+        let mut line = Vec::new();
+        while reader.read_until(b'\n', &mut line)? > 0 {
+            stats.lines += 1;
+            bytes += line.len();
+            if lossy {
+                pending.push_str(&String::from_utf8_lossy(&line));
+            } else {
+                let text = std::str::from_utf8(&line)
+                    .map_err(|err| MarkovError::Io(format!("line {}: {err}", stats.lines)))?;
+                pending.push_str(text);
+            }
+            let blank = line.iter().all(u8::is_ascii_whitespace);
+            line.clear();
+            // the last sentence might go on in the next line, so only train
+            // up to the last punctuation followed by whitespace, unless a
+            // blank line or the size cap ends it
+            let ended = if blank || pending.len() >= MAX_PENDING_BYTES {
+                pending.len()
+            } else {
+                pending[scanned..]
+                    .char_indices()
+                    .rfind(|(idx, c)| {
+                        c.is_whitespace() && pending[..scanned + idx].ends_with(['.', '!', '?'])
+                    })
+                    .map_or(0, |(idx, _)| scanned + idx)
+            };
+            if ended > 0 {
+                stats.tokens += self.train_sentences(&pending[..ended], tokenizer.as_ref(), weight);
+                pending.drain(..ended);
+            }
+            scanned = pending.len();
+            if let Some(report) = &mut progress {
+                if stats.tokens - reported < every {
+                    continue;
                 }
-                self.newest_id += 1;
-                let value = MarkovNode {
-                    data: to_word,
-                    id: self.newest_id,
-                    links: map_create(),
+                reported = stats.tokens;
+                let current = TrainProgress {
+                    bytes,
+                    lines: stats.lines,
+                    tokens: stats.tokens,
+                    nodes: self.nodes.len(),
                 };
-                self.nodes.push(value);
-                self.nodes[i].links.add(self.newest_id, 1);
+                if report(current).is_break() {
+                    stats.new_nodes = self.nodes.len() - nodes;
+                    return Ok(stats);
+                }
             }
-            i += 1;
         }
+        stats.tokens += self.train_sentences(&pending, tokenizer.as_ref(), weight);
+        stats.new_nodes = self.nodes.len() - nodes;
+        Ok(stats)
+    }
+    #[doc = "The set_keep_punctuation function switches the chain to a"]
+    #[doc = "[PunctuationTokenizer::sentence] (or back to a [SimpleTokenizer]), so"]
+    #[doc = "that . , ! and ? are trained as their own tokens (so sentences end"]
+    #[doc = "with their punctuation), and [join_words] attaches the punctuation to"]
+    #[doc = "the word before it."]
+    pub fn set_keep_punctuation(&mut self, keep: bool) {
+        if keep {
+            self.set_tokenizer(PunctuationTokenizer::sentence());
+        } else {
+            self.set_tokenizer(SimpleTokenizer::default());
+        }
+    }
+    #[doc = "The train_text_with function trains the text like"]
+    #[doc = "[MarkovChain::train_text], but splits it with the provided [Tokenizer]"]
+    #[doc = "instead of the chain's own."]
+    pub fn train_text_with(&mut self, text: &str, tokenizer: &impl Tokenizer) {
+        self.train_sentences(text, tokenizer, 1);
+    }
+    #[doc = "The train_words function trains already tokenized words exactly as"]
+    #[doc = "they are given (no cleanup, case folding, stopwords or normalizer),"]
+    #[doc = "linking the root to the first word, every word to the next, and the"]
+    #[doc = "last word to the terminal. Empty strings are skipped. Since the"]
+    #[doc = "words aren't normalized, look them up with [MarkovChain::find_token]."]
+    pub fn train_words<S: AsRef<str>>(&mut self, words: &[S]) {
+        let words: Vec<String> = words.iter().map(|word| word.as_ref().to_string()).collect();
+        self.train_words_owned(words);
+    }
+    #[doc = "The train_words_owned function is the same as"]
+    #[doc = "[MarkovChain::train_words] but takes the words by value, so they"]
+    #[doc = "don't need to be copied."]
+    pub fn train_words_owned(&mut self, mut words: Vec<String>) {
+        words.retain(|word| !word.is_empty());
+        self.train_sequence(&words, 1, true);
+    }
+    #[doc = "The train_words_weighted function trains the words like"]
+    #[doc = "[MarkovChain::train_words], but adds weight portions to every link"]
+    #[doc = "instead of one. A weight of 0 trains nothing."]
+    pub fn train_words_weighted<S: AsRef<str>>(&mut self, words: &[S], weight: u64) {
+        if weight == 0 {
+            return;
+        }
+        let words: Vec<String> = words
+            .iter()
+            .map(|word| word.as_ref().to_string())
+            .filter(|word| !word.is_empty())
+            .collect();
+        self.train_sequence(&words, weight, true);
+    }
+    #[doc = "The train_sequence function links the root to the first node of"]
+    #[doc = "the words and every node to the next, adding weight portions each time."]
+    #[doc = "If terminal is set, the last node is also linked to the terminal"]
+    #[doc = "(the root), so generation can end there. Higher-order chains train"]
+    #[doc = "the words into their lower-order chain too."]
+    fn train_sequence(&mut self, tokens: &[String], weight: u64, terminal: bool) {
+        if let Some(lower) = &mut self.lower_order {
+            lower.train_sequence(tokens, weight, terminal);
+        }
+        let mut previous = self.root.id;
+        for context in self.contexts(tokens) {
+            let id = self.find_or_create_node(&context);
+            self.add_portions(previous, id, weight);
+            previous = id;
+        }
+        if terminal && previous != self.root.id {
+            self.add_portions(previous, self.root.id, weight);
+        }
+        self.total_tokens_trained += tokens.len() as u64 * weight;
+        if let Some(novelty) = &mut self.novelty {
+            novelty.remember(tokens);
+        }
+    }
+    #[doc = "The split_link function is the [SplitLink] of higher-order text"]
+    #[doc = "chains, matching what train_sequence trains the lower-order chain"]
+    #[doc = "with: a link out of the root into a full node of order words is the"]
+    #[doc = "link into its first order - 1 words and the link from them to its"]
+    #[doc = "last order - 1 words, every other link is the link between the last"]
+    #[doc = "order - 1 words of both nodes. Nodes of fewer words are the same in"]
+    #[doc = "the lower-order chain."]
+    fn split_link(
+        &self,
+        from: MarkovNodeID,
+        to: MarkovNodeID,
+    ) -> Vec<(Option<String>, Option<String>)> {
+        let lower_order = self.order - 1;
+        // Some(None) is the root, None a node that doesn't exist
+        let words = |id: MarkovNodeID| -> Option<Option<Vec<&str>>> {
+            if id == self.root.id {
+                return Some(None);
+            }
+            self.node(id)
+                .map(|node| Some(node.data.split(' ').collect()))
+        };
+        let last_words =
+            |words: &[&str]| words[words.len().saturating_sub(lower_order)..].join(" ");
+        let (from, to) = match (words(from), words(to)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return Vec::new(),
+        };
+        match (from, to) {
+            (None, None) => Vec::new(),
+            (None, Some(to)) if to.len() > lower_order => {
+                let first = to[..lower_order].join(" ");
+                vec![
+                    (None, Some(first.clone())),
+                    (Some(first), Some(last_words(&to))),
+                ]
+            }
+            (None, Some(to)) => vec![(None, Some(to.join(" ")))],
+            (Some(from), None) => vec![(Some(last_words(&from)), None)],
+            (Some(from), Some(to)) => vec![(Some(last_words(&from)), Some(last_words(&to)))],
+        }
+    }
+    #[doc = "The train_chars function trains the text one character at a time"]
+    #[doc = "instead of one word at a time, for generating things like names with"]
+    #[doc = "[MarkovChain::generate_chars]. Every whitespace-separated part of"]
+    #[doc = "the text is trained as its own sequence ending at the terminal, so"]
+    #[doc = "generated names end too, and the text is only lowercased (unless"]
+    #[doc = "the chain is case-sensitive), punctuation is kept."]
+    #[doc = "Characters are never split, even if they take more than one byte."]
+    pub fn train_chars(&mut self, text: &str) {
+        for part in text.split_whitespace() {
+            let part = self.fold_case(part);
+            let chars: Vec<String> = part.chars().map(|c| c.to_string()).collect();
+            self.train_sequence(&chars, 1, true);
+        }
+    }
+    #[doc = "The train_word function adds a portion to the link from one word"]
+    #[doc = "to the other, creating either word if needed. Nothing is trained if"]
+    #[doc = "either word is empty or a stopword."]
+    pub fn train_word(&mut self, from_word: &str, to_word: &str) {
+        let words = self.training_tokens(vec![from_word.to_string(), to_word.to_string()]);
+        if words.len() != 2 || words.iter().any(|word| word.is_empty()) {
+            return;
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(from = %words[0], to = %words[1], "trained word");
+        let from = self.find_or_create_node(words[0].as_str());
+        let to = self.find_or_create_node(words[1].as_str());
+        self.add_transition(from, to, 1);
+        self.total_tokens_trained += 2;
+    }
+    #[doc = "The train_pairs function trains every (from, to, count) pair like"]
+    #[doc = "[MarkovChain::train_word], but adds count portions to the link in"]
+    #[doc = "one go, for building a chain from a table of precomputed bigram"]
+    #[doc = "counts. Pairs with a count of 0, an empty word or a stopword are"]
+    #[doc = "skipped."]
+    pub fn train_pairs(&mut self, pairs: &[(&str, &str, u64)]) {
+        for &(from_word, to_word, count) in pairs {
+            if count == 0 {
+                continue;
+            }
+            let words = self.training_tokens(vec![from_word.to_string(), to_word.to_string()]);
+            if words.len() != 2 || words.iter().any(|word| word.is_empty()) {
+                continue;
+            }
+            let from = self.find_or_create_node(words[0].as_str());
+            let to = self.find_or_create_node(words[1].as_str());
+            self.add_transition(from, to, count);
+            self.total_tokens_trained += 2 * count;
+        }
+    }
+    #[doc = "The add_forced_transition function adds portions to the link from"]
+    #[doc = "one word to the other (normalized like [MarkovChain::find_node]"]
+    #[doc = "normalizes them), creating the words if they aren't in the chain,"]
+    #[doc = "for rules that should hold no matter what was trained. In"]
+    #[doc = "higher-order chains the words are whole nodes, like \"the end\"."]
+    #[doc = "It returns [MarkovError::InvalidConfig] for an empty word or 0"]
+    #[doc = "portions."]
+    pub fn add_forced_transition(
+        &mut self,
+        from: &str,
+        to: &str,
+        portions: u64,
+    ) -> Result<(), MarkovError> {
+        if portions == 0 {
+            return Err(MarkovError::InvalidConfig(
+                "a forced transition needs at least 1 portion".to_string(),
+            ));
+        }
+        let from = self.normalize(from).into_owned();
+        let to = self.normalize(to).into_owned();
+        if from.is_empty() || to.is_empty() {
+            return Err(MarkovError::InvalidConfig(
+                "a forced transition can't be to or from an empty word".to_string(),
+            ));
+        }
+        let from = self.find_or_create_node(from.as_str());
+        let to = self.find_or_create_node(to.as_str());
+        self.add_transition(from, to, portions);
+        Ok(())
+    }
+    #[doc = "The set_transition_weight function sets the link from one word to"]
+    #[doc = "the other to exactly the provided portions, replacing what was"]
+    #[doc = "trained (a weight of 0 removes the link). It returns"]
+    #[doc = "[MarkovError::WordNotFound] if either word isn't in the chain."]
+    pub fn set_transition_weight(
+        &mut self,
+        from: &str,
+        to: &str,
+        portions: u64,
+    ) -> Result<(), MarkovError> {
+        let from_id = self
+            .find_node(from)
+            .ok_or_else(|| MarkovError::WordNotFound(from.to_string()))?
+            .id;
+        let to_id = self
+            .find_node(to)
+            .ok_or_else(|| MarkovError::WordNotFound(to.to_string()))?
+            .id;
+        let current = self
+            .node(from_id)
+            .and_then(|node| node.links.get(to_id))
+            .unwrap_or(0);
+        if portions > current {
+            self.add_transition(from_id, to_id, portions - current);
+        } else {
+            self.remove_transition(from_id, to_id, current - portions);
+        }
+        Ok(())
+    }
+    #[doc = "The rename_node function changes the word of the node holding"]
+    #[doc = "old_word to new_word, for cleaning up a chain after training (like"]
+    #[doc = "turning \"colour\" into \"color\"). If new_word already has a node,"]
+    #[doc = "the two are merged: their links are added together and every link"]
+    #[doc = "into old_word now goes into new_word. It returns"]
+    #[doc = "[MarkovError::WordNotFound] if old_word isn't in the chain."]
+    pub fn rename_node(&mut self, old_word: &str, new_word: &str) -> Result<(), MarkovError> {
+        let old = self
+            .find_node(old_word)
+            .ok_or_else(|| MarkovError::WordNotFound(old_word.to_string()))?
+            .id;
+        let new_word = self.normalize(new_word).into_owned();
+        if new_word.is_empty() {
+            return Err(MarkovError::InvalidConfig(
+                "a node can't be renamed to an empty word".to_string(),
+            ));
+        }
+        match self.find_token(new_word.as_str()).map(|node| node.id) {
+            Some(existing) if existing == old => {}
+            Some(existing) => self.merge_nodes(old, existing),
+            None => {
+                let node = self
+                    .node_mut(old)
+                    .expect("somehow find_node returned a node that doesn't exist");
+                let old_word = std::mem::replace(&mut node.data, new_word.clone());
+                self.index.remove(&old_word);
+                self.index.insert(new_word, old);
+                self.rebuild_lower_order();
+            }
+        }
+        Ok(())
+    }
+    #[doc = "The filter_vocabulary function removes every word that isn't in"]
+    #[doc = "allowed_words, along with every link into or out of it, so the chain"]
+    #[doc = "can only generate allowed words. In higher-order chains a node is"]
+    #[doc = "kept only if all of its words are allowed. The allowed words are"]
+    #[doc = "normalized like [MarkovChain::find_node] normalizes words."]
+    pub fn filter_vocabulary(&mut self, allowed_words: &HashSet<&str>) {
+        let allowed: HashSet<String> = allowed_words
+            .iter()
+            .map(|word| self.normalize(word).into_owned())
+            .collect();
+        let removed: Vec<MarkovNodeID> = self
+            .nodes
+            .iter()
+            .filter(|node| !node.data.split(' ').all(|word| allowed.contains(word)))
+            .map(|node| node.id)
+            .collect();
+        self.remove_nodes(&removed);
+    }
+    #[doc = "The generate function walks the chain from the root, picking each"]
+    #[doc = "next word at random (weighted by portions) until it reaches a word"]
+    #[doc = "with no links or has produced max_words words. It uses"]
+    #[doc = "[rand::thread_rng] (or [rand::rngs::OsRng] with the wasm feature),"]
+    #[doc = "see [MarkovChain::generate_with_rng] for reproducible output."]
+    pub fn generate(&self, max_words: usize) -> Result<Vec<String>, MarkovError> {
+        self.generate_with_rng(&mut default_rng(), max_words)
+    }
+    #[doc = "The generate_text function is the same as [MarkovChain::generate] but"]
+    #[doc = "joins the words into text with [join_words]."]
+    pub fn generate_text(&self, max_words: usize) -> Result<String, MarkovError> {
+        Ok(join_words(&self.generate(max_words)?))
+    }
+    #[doc = "The generate_chars function generates from a chain trained with"]
+    #[doc = "[MarkovChain::train_chars], joining the characters without spaces. It"]
+    #[doc = "stops after [DEFAULT_MAX_CHARS] characters, see"]
+    #[doc = "[MarkovChain::generate_chars_with_rng] to pick the limit."]
+    pub fn generate_chars(&self) -> Result<String, MarkovError> {
+        self.generate_chars_with_rng(&mut default_rng(), DEFAULT_MAX_CHARS)
+    }
+    #[doc = "The generate_chars_with_rng function is the same as"]
+    #[doc = "[MarkovChain::generate_chars] but samples with the provided rng and"]
+    #[doc = "stops after max_chars characters."]
+    pub fn generate_chars_with_rng<R: Rng>(
+        &self,
+        rng: &mut R,
+        max_chars: usize,
+    ) -> Result<String, MarkovError> {
+        Ok(self.generate_with_rng(rng, max_chars)?.concat())
+    }
+    #[doc = "The generate_with_rng function is the same as [MarkovChain::generate]"]
+    #[doc = "but does all of its sampling with the provided rng, so a seeded rng"]
+    #[doc = "gives the same words every time. The rng is not used at all if the"]
+    #[doc = "chain is empty."]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self, rng))
+    )]
+    pub fn generate_with_rng<R: Rng>(
+        &self,
+        rng: &mut R,
+        max_words: usize,
+    ) -> Result<Vec<String>, MarkovError> {
+        if self.root.links.is_empty() {
+            return Err(MarkovError::EmptyChain);
+        }
+        let mut out = Vec::new();
+        self.walk_into(&self.root, max_words, &mut out, |node| {
+            sample_link(&node.links, rng)
+        });
+        Ok(out)
+    }
+    #[doc = "The walk_into function follows links out of the provided node,"]
+    #[doc = "pushing each word it reaches onto out, until it reaches a node with"]
+    #[doc = "no links or has pushed max_words words. Leaving the root pushes every"]
+    #[doc = "word of the node reached, after that only the newest word is pushed,"]
+    #[doc = "since the rest of the context is already in out. The choose closure"]
+    #[doc = "picks the link to follow, and picking the root ends the walk."]
+    pub(crate) fn walk_into<F>(
+        &self,
+        start: &MarkovNode,
+        max_words: usize,
+        out: &mut Vec<String>,
+        mut choose: F,
+    ) where
+        F: FnMut(&MarkovNode) -> Option<MarkovNodeID>,
+    {
+        let mut current = start;
+        let mut pushed = 0;
+        while pushed < max_words {
+            let from_root = current.id == self.root.id;
+            current = match choose(current) {
+                Some(next) if next != self.root.id => self
+                    .node(next)
+                    .expect("somehow a link points to a node that doesn't exist"),
+                _ => break,
+            };
+            #[cfg(feature = "tracing")]
+            tracing::debug!(to = %current.data, "sampled transition");
+            if from_root {
+                for word in self.surface_words(current).take(max_words - pushed) {
+                    out.push(word);
+                    pushed += 1;
+                }
+            } else if let Some(word) = current.data.rsplit(' ').next() {
+                out.push(self.surface_form(word).to_string());
+                pushed += 1;
+            }
+        }
+    }
+    #[doc = "The complete_sentence function continues the provided prefix from"]
+    #[doc = "its last word (its last order words for higher-order chains),"]
+    #[doc = "generating at most max_additional_words more words, and returns the"]
+    #[doc = "prefix followed by the continuation. An empty prefix is continued"]
+    #[doc = "from the root, like [MarkovChain::generate]. See"]
+    #[doc = "[MarkovChain::complete_sentence_with_rng] for reproducible output."]
+    pub fn complete_sentence(
+        &self,
+        prefix: &[&str],
+        max_additional_words: usize,
+    ) -> Result<Vec<String>, MarkovError> {
+        self.complete_sentence_with_rng(&mut default_rng(), prefix, max_additional_words)
+    }
+    #[doc = "The complete_sentence_with_rng function is the same as"]
+    #[doc = "[MarkovChain::complete_sentence] but samples with the provided rng."]
+    pub fn complete_sentence_with_rng<R: Rng>(
+        &self,
+        rng: &mut R,
+        prefix: &[&str],
+        max_additional_words: usize,
+    ) -> Result<Vec<String>, MarkovError> {
+        let state = prefix[prefix.len().saturating_sub(self.order)..].join(" ");
+        let start = match prefix.last() {
+            Some(_) => self
+                .find_node(&state)
+                .ok_or(MarkovError::WordNotFound(state))?,
+            None => {
+                if self.root.links.is_empty() {
+                    return Err(MarkovError::EmptyChain);
+                }
+                &self.root
+            }
+        };
+        let mut out: Vec<String> = prefix.iter().map(|word| word.to_string()).collect();
+        self.walk_into(start, max_additional_words, &mut out, |node| {
+            sample_link(&node.links, rng)
+        });
+        Ok(out)
+    }
+    #[doc = "The generate_backwards function builds text that ends with the"]
+    #[doc = "provided word by walking predecessor links at random (weighted by"]
+    #[doc = "the portions of each incoming link) until it reaches the root, a"]
+    #[doc = "word with no predecessors, or max_words words (the end word counts)."]
+    #[doc = "In higher-order chains it starts from a node ending with the word."]
+    #[doc = "It returns [None] only if the end word isn't in the chain. See"]
+    #[doc = "[MarkovChain::generate_backwards_with_rng] for reproducible output."]
+    pub fn generate_backwards(&self, end_word: &str, max_words: usize) -> Option<String> {
+        self.generate_backwards_with_rng(&mut default_rng(), end_word, max_words)
+    }
+    #[doc = "The generate_backwards_with_rng function is the same as"]
+    #[doc = "[MarkovChain::generate_backwards] but samples with the provided rng."]
+    pub fn generate_backwards_with_rng<R: Rng>(
+        &self,
+        rng: &mut R,
+        end_word: &str,
+        max_words: usize,
+    ) -> Option<String> {
+        let end_word = self.normalize(end_word);
+        let candidates: Vec<&MarkovNode> = self
+            .nodes
+            .iter()
+            .filter(|node| node.data.rsplit(' ').next() == Some(end_word.as_ref()))
+            .collect();
+        let weights = candidates.iter().map(|node| {
+            let incoming = self.predecessor_ids(node.id).iter().map(|(_, p)| *p).sum();
+            (node.id, incoming)
+        });
+        let mut current = match sample_weighted(weights, rng) {
+            Some(id) => self.node(id)?,
+            None => *candidates.first()?,
+        };
+        let mut out: Vec<&str> = current
+            .data
+            .rsplit(' ')
+            .take(max_words)
+            .map(|word| self.surface_form(word))
+            .collect();
+        while out.len() < max_words {
+            let predecessors = self.predecessor_ids(current.id);
+            let previous = match sample_weighted(predecessors.into_iter(), rng) {
+                Some(id) if id != self.root.id => id,
+                _ => break,
+            };
+            current = self
+                .node(previous)
+                .expect("somehow a link comes from a node that doesn't exist");
+            match current.data.split(' ').next() {
+                Some(word) => out.push(self.surface_form(word)),
+                None => break,
+            }
+        }
+        out.reverse();
+        Some(join_words(&out))
+    }
+}
+
+#[doc = "A [MarkovChain] can be made straight from text, which is the same as"]
+#[doc = "calling [MarkovChain::train_text] on a [MarkovChain::new]."]
+impl From<&str> for MarkovChain {
+    fn from(text: &str) -> MarkovChain {
+        let mut chain = MarkovChain::new();
+        chain.train_text(text.to_string());
+        chain
+    }
+}
+
+impl From<String> for MarkovChain {
+    fn from(text: String) -> MarkovChain {
+        let mut chain = MarkovChain::new();
+        chain.train_text(text);
+        chain
+    }
+}
+
+#[doc = "Extending a [MarkovChain] with [String]s trains each one as its own"]
+#[doc = "text with [MarkovChain::train_text]."]
+impl Extend<String> for MarkovChain {
+    fn extend<I: IntoIterator<Item = String>>(&mut self, texts: I) {
+        for text in texts {
+            self.train_text(text);
+        }
+    }
+}
+
+#[doc = "Extending a [MarkovChain] with (from, to) pairs trains each pair with"]
+#[doc = "[MarkovChain::train_word]."]
+impl Extend<(String, String)> for MarkovChain {
+    fn extend<I: IntoIterator<Item = (String, String)>>(&mut self, pairs: I) {
+        for (from, to) in pairs {
+            self.train_word(&from, &to);
+        }
+    }
+}
+
+impl<T: Eq + Hash + Clone + fmt::Display> MarkovChain<T> {
+    #[doc = "The summary function returns an overview of the chain: its size, the"]
+    #[doc = "[SUMMARY_WORDS] words with the highest out-degree and the"]
+    #[doc = "[SUMMARY_WORDS] words sentences start with most, most first."]
+    pub fn summary(&self) -> ChainSummary {
+        let transitions: usize = std::iter::once(&self.root)
+            .chain(self.nodes.iter())
+            .map(|node| node.links.len())
+            .sum();
+        let mut top_words: Vec<(String, usize)> = self
+            .nodes
+            .iter()
+            .map(|node| (node.data.to_string(), self.out_degree(node)))
+            .collect();
+        top_words.sort_by_key(|(_, degree)| std::cmp::Reverse(*degree));
+        top_words.truncate(SUMMARY_WORDS);
+        let mut start_words: Vec<(String, u64)> = self
+            .root
+            .links
+            .iter()
+            .filter_map(|(to, portions)| Some((self.node(*to)?.data.to_string(), *portions)))
+            .collect();
+        start_words.sort_by_key(|(_, portions)| std::cmp::Reverse(*portions));
+        start_words.truncate(SUMMARY_WORDS);
+        ChainSummary {
+            words: self.nodes.len(),
+            transitions,
+            total_portions: self.all_portions,
+            order: self.order,
+            top_words,
+            start_words,
+        }
+    }
+}
+
+#[doc = "The write_summary_words function writes a line of (word, count) pairs"]
+#[doc = "for displaying a [MarkovChain], cutting long words off and ending"]
+#[doc = "with an ellipsis if there were more than total words to list."]
+fn write_summary_words<N: fmt::Display>(
+    f: &mut fmt::Formatter<'_>,
+    label: &str,
+    words: &[(String, N)],
+    total: usize,
+) -> fmt::Result {
+    write!(f, "\n{label}:")?;
+    for (i, (word, count)) in words.iter().enumerate() {
+        let sep = if i == 0 { " " } else { ", " };
+        if word.chars().count() > SUMMARY_WORD_CHARS {
+            let cut: String = word.chars().take(SUMMARY_WORD_CHARS - 1).collect();
+            write!(f, "{sep}{cut}\u{2026} ({count})")?;
+        } else {
+            write!(f, "{sep}{word} ({count})")?;
+        }
+    }
+    if total > words.len() {
+        write!(f, ", \u{2026}")?;
+    }
+    Ok(())
+}
+
+#[doc = "Displaying a [MarkovChain] gives a short summary from"]
+#[doc = "[MarkovChain::summary], unlike the [Debug] output which dumps every"]
+#[doc = "node. Transitions and total_portions both count the links out of the"]
+#[doc = "root. The output stays the same size however big the chain gets."]
+impl<T: Eq + Hash + Clone + fmt::Display> fmt::Display for MarkovChain<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let summary = self.summary();
+        write!(
+            f,
+            "MarkovChain {{ words: {}, transitions: {}, total_portions: {}, order: {} }}",
+            summary.words, summary.transitions, summary.total_portions, summary.order
+        )?;
+        write_summary_words(f, "top words", &summary.top_words, self.nodes.len())?;
+        write_summary_words(
+            f,
+            "start words",
+            &summary.start_words,
+            self.root.links.len(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn order_two_nodes_are_word_pairs() {
+        let mut chain = create_markov_chain_with_order(2).unwrap();
+        chain.train_text("the cat sat on the mat".to_string());
+        let contexts: Vec<&str> = chain.nodes.iter().map(|node| node.data.as_str()).collect();
+        assert_eq!(
+            contexts,
+            ["the cat", "cat sat", "sat on", "on the", "the mat"]
+        );
+        let generated = chain
+            .generate_with_rng(&mut StdRng::seed_from_u64(42), 100)
+            .unwrap();
+        assert_eq!(generated, ["the", "cat", "sat", "on", "the", "mat"]);
+        let completed = chain.complete_sentence(&["sat", "on"], 2).unwrap();
+        assert_eq!(completed, ["sat", "on", "the", "mat"]);
+        assert_eq!(
+            chain.generate_backwards("mat", 3),
+            Some("on the mat".to_string())
+        );
+    }
+
+    fn lower_order_links(chain: &MarkovChain) -> Vec<Vec<(String, String, u64)>> {
+        let mut out = Vec::new();
+        let mut lower = chain.lower_order.as_deref();
+        while let Some(chain) = lower {
+            let mut links: Vec<(String, String, u64)> = std::iter::once(&chain.root)
+                .chain(&chain.nodes)
+                .flat_map(|node| {
+                    node.links.iter().map(|(to, portions)| {
+                        let to = chain.node(*to).unwrap();
+                        (node.data.clone(), to.data.clone(), *portions)
+                    })
+                })
+                .collect();
+            links.sort();
+            out.push(links);
+            lower = chain.lower_order.as_deref();
+        }
+        out
+    }
+
+    #[test]
+    fn lower_orders_follow_every_change() {
+        let mut chain = create_markov_chain_with_order(3).unwrap();
+        chain.train_text("the cat sat on the mat. a cat. the cat sat down.".to_string());
+        let trained = lower_order_links(&chain);
+        assert_eq!(trained.len(), 2);
+        chain.rebuild_lower_order();
+        assert_eq!(lower_order_links(&chain), trained);
+
+        let mut chain = create_markov_chain_with_order(2).unwrap();
+        chain.train_text("the cat sat".to_string());
+        chain.train_word("cat", "ran");
+        chain
+            .add_forced_transition("cat sat", "sat down", 2)
+            .unwrap();
+        let pair = |from: &str, to: &str, portions| (from.to_string(), to.to_string(), portions);
+        assert_eq!(
+            lower_order_links(&chain)[0],
+            [
+                pair("", "the", 1),
+                pair("cat", "ran", 1),
+                pair("cat", "sat", 1),
+                pair("sat", "", 1),
+                pair("sat", "down", 2),
+                pair("the", "cat", 1),
+            ]
+        );
+        chain
+            .set_transition_weight("cat sat", "sat down", 0)
+            .unwrap();
+        chain.rename_node("cat", "dog").unwrap();
+        assert_eq!(
+            lower_order_links(&chain)[0],
+            [
+                pair("", "the", 1),
+                pair("cat", "sat", 1),
+                pair("dog", "ran", 1),
+                pair("sat", "", 1),
+                pair("the", "cat", 1),
+            ]
+        );
+        chain.filter_vocabulary(&HashSet::from(["the", "cat"]));
+        assert_eq!(
+            lower_order_links(&chain)[0],
+            [pair("", "the", 1), pair("the", "cat", 1)]
+        );
+    }
+
+    #[test]
+    fn sentences_are_completed_reproducibly() {
+        let mut chain = MarkovChain::new();
+        assert_eq!(
+            chain.complete_sentence_with_rng(&mut StdRng::seed_from_u64(0), &[], 5),
+            Err(MarkovError::EmptyChain)
+        );
+        chain.train_text("the cat sat. the dog ran. the cat ran.".to_string());
+        let complete = |seed: u64, prefix: &[&str], max: usize| {
+            chain
+                .complete_sentence_with_rng(&mut StdRng::seed_from_u64(seed), prefix, max)
+                .unwrap()
+        };
+        let mut seconds = HashSet::new();
+        for seed in 0..30 {
+            let completed = complete(seed, &["the"], 5);
+            assert_eq!(completed, complete(seed, &["the"], 5));
+            assert_eq!(completed[0], "the");
+            assert!(completed.len() <= 3);
+            seconds.insert(completed[1].clone());
+        }
+        assert_eq!(
+            seconds,
+            HashSet::from(["cat".to_string(), "dog".to_string()])
+        );
+        assert_eq!(complete(0, &["the"], 0), ["the"]);
+        assert_eq!(complete(0, &["a", "dog"], 5), ["a", "dog", "ran"]);
+        assert_eq!(
+            chain.complete_sentence_with_rng(&mut StdRng::seed_from_u64(0), &["zebra"], 5),
+            Err(MarkovError::WordNotFound("zebra".to_string()))
+        );
+    }
+
+    #[test]
+    fn backwards_generation_is_reproducible() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("the cat ran. a dog ran. the cat ran. big cat".to_string());
+        let backwards = |seed: u64, max: usize| {
+            chain.generate_backwards_with_rng(&mut StdRng::seed_from_u64(seed), "ran", max)
+        };
+        let mut texts = HashSet::new();
+        for seed in 0..50 {
+            let text = backwards(seed, 10).unwrap();
+            assert_eq!(Some(&text), backwards(seed, 10).as_ref());
+            texts.insert(text);
+        }
+        // "ran" follows "cat" and "dog", "cat" follows "the" and "big"
+        assert_eq!(
+            texts,
+            HashSet::from(["the cat ran", "a dog ran", "big cat ran"].map(String::from))
+        );
+        assert_eq!(backwards(0, 1).unwrap(), "ran");
+        assert_eq!(backwards(0, 0).unwrap(), "");
+        assert_eq!(
+            chain.generate_backwards_with_rng(&mut StdRng::seed_from_u64(0), "zebra", 5),
+            None
+        );
+    }
+
+    #[test]
+    fn chains_work_over_any_token_type() {
+        #[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+        enum Note {
+            #[default]
+            C,
+            E,
+            G,
+        }
+        let mut chain: MarkovChain<Note> = create_token_chain();
+        chain.train_tokens(&[Note::C, Note::E, Note::G]);
+        chain.train_token_pair(Note::G, Note::C);
+        assert_eq!(chain.find_token(&Note::E).unwrap().links.len(), 1);
+        assert_eq!(chain.get_probability(&Note::G, &Note::C), 1.0);
+        let generated = chain
+            .generate_tokens_with_rng(&mut StdRng::seed_from_u64(1), 4)
+            .unwrap();
+        assert_eq!(generated, [Note::C, Note::E, Note::G, Note::C]);
+
+        let text: TextChain = MarkovChain::new();
+        assert!(text.find_node("anything").is_none());
+    }
+
+    #[test]
+    fn display_is_a_summary() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("the cat saw the dog".to_string());
+        assert_eq!(
+            chain.to_string(),
+            "MarkovChain { words: 4, transitions: 6, total_portions: 6, order: 1 }\n\
+             top words: the (2), cat (1), saw (1), dog (0)\n\
+             start words: the (1)"
+        );
+    }
+
+    #[test]
+    fn summary_lists_are_bounded() {
+        let mut chain = MarkovChain::new();
+        let long = "a".repeat(30);
+        for i in 0..15 {
+            chain.train_text(format!("w{i} {long} x{i}"));
+        }
+        let summary = chain.summary();
+        assert_eq!(summary.words, 31);
+        assert_eq!(summary.top_words.len(), SUMMARY_WORDS);
+        assert_eq!(summary.top_words[0], (long.clone(), 15));
+        assert_eq!(summary.start_words.len(), SUMMARY_WORDS);
+        let shown = chain.to_string();
+        assert!(!shown.contains(&long));
+        assert!(shown.contains(&format!(
+            "{}\u{2026} (15)",
+            "a".repeat(SUMMARY_WORD_CHARS - 1)
+        )));
+        assert_eq!(shown.lines().count(), 3);
+        assert!(shown
+            .lines()
+            .all(|line| line.ends_with(", \u{2026}") || line.starts_with("MarkovChain")));
+    }
+
+    #[test]
+    fn chains_can_be_made_from_text() {
+        let chain: MarkovChain = "hello world foo bar".into();
+        assert_eq!(chain.nodes.len(), 4);
+        let chain = MarkovChain::from(String::from("hello world"));
+        assert_eq!(chain.get_probability("hello", "world"), 1.0);
+    }
+
+    #[test]
+    fn intersection_keeps_shared_links() {
+        let mut a = MarkovChain::new();
+        a.train_text("the cat sat on the mat".to_string());
+        a.train_text("the cat sat".to_string());
+        let mut b = MarkovChain::new();
+        b.train_text("the cat sat by the dog".to_string());
+        let both = a.intersection(&b);
+        assert!(both.is_valid());
+        assert_eq!(both.root.links.len(), 1);
+        assert_eq!(both.most_common_transitions(10).len(), 2);
+        assert_eq!(both.get_probability("cat", "sat"), 1.0);
+        assert_eq!(
+            both.find_node("the")
+                .unwrap()
+                .links
+                .get(both.find_node("cat").unwrap().id),
+            Some(1)
+        );
+        assert!(both.find_node("mat").is_none());
+        assert!(both.find_node("dog").is_none());
+    }
+
+    #[test]
+    fn subtracting_a_chain_from_itself_empties_it() {
+        let mut a = MarkovChain::new();
+        a.train_text("a b".to_string());
+        a.subtract(&a.clone());
+        assert_eq!(a.all_portions(), 0);
+        assert!(a.to_transition_pairs().is_empty());
+        assert!(a.is_valid());
+        // the words themselves are kept
+        assert!(a.find_node("b").is_some());
+    }
+
+    #[test]
+    fn subtracting_stops_at_zero() {
+        let mut a = MarkovChain::new();
+        a.train_text("a b. a c".to_string());
+        a.train_text("a b".to_string());
+        let mut b = MarkovChain::new();
+        for _ in 0..5 {
+            b.train_text("a b".to_string());
+        }
+        b.train_text("z".to_string());
+        a.subtract(&b);
+        assert!(a.is_valid());
+        // the root to a, a to b and b to the end only had 3 and 2 portions
+        // to lose 5 from, a c and its end are untouched
+        assert!(a.root.links.is_empty());
+        assert_eq!(a.find_node("a").unwrap().links.len(), 1);
+        assert_eq!(a.get_probability("a", "c"), 1.0);
+        assert!(a.find_node("b").unwrap().links.is_empty());
+        assert!(a.find_node("z").is_none());
+        assert_eq!(a.all_portions(), 2);
+    }
+
+    #[test]
+    fn kept_punctuation_ends_sentences() {
+        let mut chain = MarkovChain::new();
+        chain.set_keep_punctuation(true);
+        chain.train_text("Oh, hi. Bye!".to_string());
+        let period = chain.find_node(".").unwrap();
+        assert_eq!(period.links.get(chain.root.id), Some(1));
+        assert!(chain
+            .find_node(",")
+            .unwrap()
+            .links
+            .get(chain.root.id)
+            .is_none());
+        // every sentence ends at its punctuation, nothing links "." to "bye"
+        assert_eq!(period.links.len(), 1);
+        let text = chain.generate_text(100).unwrap();
+        assert!(text == "oh, hi." || text == "bye!", "{text}");
+    }
+
+    #[test]
+    fn chars_are_never_split() {
+        let mut chain = create_markov_chain_with_order(2).unwrap();
+        chain.train_chars("café Ünïcødé, naïve");
+        assert!(chain.find_node("é Ü").is_none());
+        assert!(chain.find_node("é ,").is_some());
+        assert!(chain.find_node("d é").is_some());
+        for _ in 0..20 {
+            let generated = chain.generate_chars().unwrap();
+            assert!(!generated.contains(' '));
+            assert!(generated.chars().count() <= DEFAULT_MAX_CHARS);
+        }
+
+        // every name ends, so they stay far below the cap
+        let mut chain = MarkovChain::new();
+        chain.train_chars("anna bob");
+        for seed in 0..20 {
+            let generated = chain
+                .generate_chars_with_rng(&mut StdRng::seed_from_u64(seed), DEFAULT_MAX_CHARS)
+                .unwrap();
+            assert!(generated.chars().count() < DEFAULT_MAX_CHARS, "{generated}");
+        }
+    }
+
+    #[test]
+    fn short_text_trains_a_shorter_node() {
+        let mut chain = create_markov_chain_with_order(3).unwrap();
+        chain.train_text("hello there".to_string());
+        assert!(chain.find_node("hello there").is_some());
+        assert_eq!(chain.generate(10).unwrap(), ["hello", "there"]);
+    }
+
+    #[test]
+    fn case_sensitivity_is_consistent() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("Paris and paris".to_string());
+        assert!(chain.find_node("PARIS").is_some());
+        assert_eq!(chain.nodes.len(), 2);
+
+        let mut chain = MarkovChainBuilder::new()
+            .case_sensitive(true)
+            .build()
+            .unwrap();
+        chain.train_text("Paris and paris".to_string());
+        assert_eq!(chain.nodes.len(), 3);
+        assert!(chain.find_node("PARIS").is_none());
+        assert_eq!(chain.find_nodes_ignoring_case("PARIS").len(), 2);
+    }
+
+    #[test]
+    fn renaming_onto_an_existing_word_merges() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("the colour red. the color red. colour me".to_string());
+        let total = chain.all_portions();
+        chain.rename_node("colour", "color").unwrap();
+        assert!(chain.find_node("colour").is_none());
+        let color = chain.find_node("color").unwrap();
+        assert_eq!(color.links.get(chain.find_node("red").unwrap().id), Some(2));
+        let the = chain.find_node("the").unwrap();
+        assert_eq!(the.links.get(color.id), Some(2));
+        assert_eq!(chain.all_portions(), total);
+        assert!(chain.is_valid());
+
+        chain.rename_node("red", "blue").unwrap();
+        assert!(chain.find_node("blue").is_some());
+        assert!(chain.is_valid());
+        assert_eq!(
+            chain.rename_node("green", "blue"),
+            Err(MarkovError::WordNotFound("green".to_string()))
+        );
+    }
+
+    #[test]
+    fn get_or_insert_only_inserts_missing_keys() {
+        let mut map = map_from(&[("a", 1)]);
+        *map.get_or_insert("a", 10) += 1;
+        *map.get_or_insert("b", 10) += 1;
+        assert_eq!(map.get("a"), Some(2));
+        assert_eq!(map.get("b"), Some(11));
+        let mut called = false;
+        map.get_or_insert_with("a", || {
+            called = true;
+            0
+        });
+        assert!(!called);
+        assert_eq!(*map.get_or_insert_with("c", || 5), 5);
+        assert_eq!(map.len(), 3);
+    }
+
+    #[test]
+    fn maps_can_be_consumed_into_vecs() {
+        let map = map_from(&[("a", 1), ("b", 2)]);
+        assert_eq!(map.clone().into_vec(), [("a", 1), ("b", 2)]);
+        assert_eq!(map.clone().into_keys(), ["a", "b"]);
+        assert_eq!(map.into_values(), [1, 2]);
+        assert!(Map::<u8, u8>::new().into_vec().is_empty());
+    }
+
+    #[test]
+    fn maps_sort_their_keys_and_values_together() {
+        let mut map = map_from(&[("c", 1), ("a", 3), ("b", 1)]);
+        map.sort_by_key();
+        assert_eq!(map.clone().into_vec(), [("a", 3), ("b", 1), ("c", 1)]);
+        map.sort_by_value();
+        assert_eq!(map.clone().into_vec(), [("b", 1), ("c", 1), ("a", 3)]);
+        map.sort_by(|a, b| b.1.cmp(a.1).then(b.0.cmp(a.0)));
+        assert_eq!(map.get("c"), Some(1));
+        assert_eq!(map.into_keys(), ["a", "c", "b"]);
+    }
+
+    #[test]
+    fn capacity_is_allocated_up_front() {
+        let map: Map<u8, u8> = Map::with_capacity(8);
+        assert!(map.is_empty());
+        assert!(map.into_keys().capacity() >= 8);
+        let mut chain = MarkovChainBuilder::new().vocabulary(100).build().unwrap();
+        assert!(chain.nodes.capacity() >= 100);
+        chain.train_text("still trains as usual".to_string());
+        assert_eq!(chain.nodes.len(), 4);
+    }
+
+    #[test]
+    fn maps_iterate_by_reference() {
+        let map = map_from(&[("a", 1), ("b", 2)]);
+        let mut total = 0;
+        for (_, value) in &map {
+            total += value;
+        }
+        assert_eq!(total, 3);
+        assert_eq!(map.len(), 2);
+        let owned: Vec<(&str, i32)> = map.into_iter().collect();
+        assert_eq!(owned, [("a", 1), ("b", 2)]);
+    }
+
+    #[test]
+    fn duplicate_keys_are_merged() {
+        let mut map = Map::new();
+        map.insert("a", 1);
+        map.insert("b", 2);
+        map.insert("a", 3);
+        map.insert("a", 4);
+        map.dedup(|a, b| a + b);
+        assert_eq!(map.into_vec(), [("a", 8), ("b", 2)]);
+    }
+
+    #[test]
+    fn maps_can_be_looked_up_without_panicking() {
+        let mut map = Map::from_pairs(&[("a", 1), ("b", 2)]);
+        assert_eq!(map.try_get(&"b"), Some(&2));
+        assert_eq!(map.try_get(&"c"), None);
+        *map.get_mut(&"a").unwrap() += 10;
+        assert_eq!(map["a"], 11);
+        assert!(map.get_mut(&"c").is_none());
+        let missing = std::panic::catch_unwind(|| map["c"]).unwrap_err();
+        assert_eq!(
+            missing.downcast_ref::<String>().unwrap(),
+            "cannot find key in [Map] of 2 keys"
+        );
+    }
+
+    #[test]
+    fn maps_pop_and_peek_at_their_ends() {
+        let mut map: Map<&str, u64> = Map::new();
+        assert_eq!(map.first(), None);
+        assert_eq!(map.last(), None);
+        assert_eq!(map.pop(), None);
+
+        map.insert("a", 1);
+        assert_eq!(map.first(), Some((&"a", &1)));
+        assert_eq!(map.last(), Some((&"a", &1)));
+        map.insert("b", 2);
+        map.insert("c", 3);
+        assert_eq!(map.first(), Some((&"a", &1)));
+        assert_eq!(map.last(), Some((&"c", &3)));
+        assert_eq!(map.pop(), Some(("c", 3)));
+        assert_eq!(map.last(), Some((&"b", &2)));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.pop(), Some(("b", 2)));
+        assert_eq!(map.pop(), Some(("a", 1)));
+        assert_eq!(map.pop(), None);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn stopwords_are_skipped_and_their_neighbors_linked() {
+        let mut chain = MarkovChain::new();
+        chain.set_stopwords(["The".to_string(), "a".to_string()]);
+        assert!(chain.is_stopword("THE"));
+        chain.train_text("the cat sat on a mat".to_string());
+        assert!(chain.find_node("the").is_none());
+        let on = chain.find_node("on").unwrap();
+        assert!(on.links.has(chain.find_node("mat").unwrap().id));
+        assert_eq!(chain.root.links.len(), 1);
+
+        chain.clear_stopwords();
+        chain.train_text("the end".to_string());
+        assert!(chain.find_node("the").is_some());
+    }
+
+    #[test]
+    fn normalized_words_share_a_node_but_keep_their_surface() {
+        let mut chain = MarkovChain::new();
+        chain.set_normalizer(|word| match word {
+            "running" | "runs" | "ran" => "run".to_string(),
+            word => word.to_string(),
+        });
+        chain.train_text("I was running home".to_string());
+        chain.train_text("she runs home".to_string());
+        chain.train_text("he ran away".to_string());
+        let run = chain.find_node("RUNS").unwrap();
+        assert_eq!(run.data, "run");
+        assert!(chain
+            .find_node("running")
+            .is_some_and(|node| node.id == run.id));
+        assert_eq!(run.links.len(), 2);
+        assert_eq!(chain.surface_form("run"), "running");
+        for _ in 0..20 {
+            let words = chain.generate(10).unwrap();
+            assert!(!words.iter().any(|word| word == "run"), "{words:?}");
+        }
+    }
+
+    #[test]
+    fn filtered_chains_only_keep_allowed_words() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("the cat ate the rat".to_string());
+        chain.filter_vocabulary(&HashSet::from(["The", "cat", "rat"]));
+        assert!(chain.find_node("ate").is_none());
+        assert!(chain
+            .find_node("the")
+            .unwrap()
+            .links
+            .has(chain.find_node("rat").unwrap().id));
+        assert!(chain.find_node("cat").unwrap().links.is_empty());
+        assert!(chain.is_valid());
+    }
+
+    #[test]
+    fn sentences_are_trained_separately() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("The end. Next one... Really?! yes".to_string());
+        let end = chain.find_node("end").unwrap();
+        assert_eq!(end.links.get(chain.root.id), Some(1));
+        assert!(!end.links.has(chain.find_node("next").unwrap().id));
+        assert_eq!(chain.root.links.len(), 4);
+        assert!(chain.find_node("yes").unwrap().links.has(chain.root.id));
+        assert!(chain.is_valid());
+    }
+
+    #[test]
+    fn pre_tokenized_words_are_trained_as_given() {
+        let mut chain = MarkovChain::new();
+        chain.train_words(&["Hello", "", "World!"]);
+        let hello = chain.find_token("Hello").unwrap();
+        let world = chain.find_token("World!").unwrap();
+        assert!(chain.root.links.has(hello.id));
+        assert_eq!(hello.links.get(world.id), Some(1));
+        assert_eq!(world.links.get(chain.root.id), Some(1));
+        chain.train_words_owned(vec!["Hello".to_string(), "World!".to_string()]);
+        assert_eq!(chain.all_portions(), 6);
+    }
+
+    #[test]
+    fn pairs_are_trained_with_their_counts() {
+        let mut chain = MarkovChain::new();
+        chain.train_pairs(&[("The", "cat", 4812), ("cat", "sat", 3), ("cat", "", 7)]);
+        chain.train_pairs(&[("the", "cat", 8), ("sat", "down", 0)]);
+        let cat = chain.find_node("cat").unwrap();
+        let the = chain.find_node("the").unwrap();
+        assert_eq!(the.links.get(cat.id), Some(4820));
+        assert_eq!(cat.links.len(), 1);
+        assert!(chain.find_node("down").is_none());
+        assert_eq!(chain.all_portions(), 4823);
+        assert!(chain.is_valid());
+    }
+
+    #[test]
+    fn lines_are_trained_separately() {
+        let mut chain = MarkovChain::new();
+        chain.train_lines("hi there\n\n  \n...\nhow are you\r\nfine");
+        let there = chain.find_node("there").unwrap();
+        assert!(!there.links.has(chain.find_node("how").unwrap().id));
+        assert_eq!(there.links.get(chain.root.id), Some(1));
+        assert_eq!(chain.root.links.len(), 3);
+        assert!(chain.is_valid());
+    }
+
+    #[test]
+    fn reading_trains_the_same_as_the_whole_text() {
+        let text = "The cat sat.\nIt sat on\nthe mat! Then\nit left.\n\nok";
+        let mut whole = MarkovChain::new();
+        whole.train_text(text.to_string());
+        let mut read = MarkovChain::new();
+        let stats = read
+            .train_from_reader(std::io::Cursor::new(text.as_bytes()))
+            .unwrap();
+        assert_eq!(
+            stats,
+            TrainStats {
+                lines: 6,
+                tokens: 12,
+                new_nodes: 9,
+            }
+        );
+        assert_eq!(read.to_json(), whole.to_json());
+
+        // a blank line ends a sentence, and so does the size cap
+        let mut read = MarkovChain::new();
+        read.train_from_reader(std::io::Cursor::new("the cat\n\nsat"))
+            .unwrap();
+        let mut sentences = MarkovChain::new();
+        sentences.train_from_sentences(["the cat", "sat"]);
+        assert_eq!(read.to_json(), sentences.to_json());
+        let mut read = MarkovChain::new();
+        let text = "word ".repeat(MAX_PENDING_BYTES / 5) + "\n";
+        read.train_from_reader(std::io::Cursor::new(text.repeat(3)))
+            .unwrap();
+        let word = read.find_node("word").unwrap().id;
+        assert_eq!(read.root.links.get(word), Some(3));
+
+        let invalid = std::io::Cursor::new(b"fine\n\xff\n".to_vec());
+        assert!(matches!(
+            read.train_from_reader(invalid),
+            Err(MarkovError::Io(_))
+        ));
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[test]
+    fn files_are_trained_strictly_or_lossily() {
+        let path = std::env::temp_dir().join("markov-chain-rs-train-from-file.txt");
+        std::fs::write(&path, b"good text.\nbad \xff byte").unwrap();
+        let mut chain = MarkovChain::new();
+        assert!(matches!(
+            chain.train_from_file(&path),
+            Err(MarkovError::Io(_))
+        ));
+        let stats = chain.train_from_file_lossy(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(stats.lines, 2);
+        assert!(chain.find_node("byte").is_some());
+        assert!(chain.find_node("bad").is_some());
+
+        let missing = std::env::temp_dir().join("markov-chain-rs-missing.txt");
+        assert_eq!(
+            chain.train_from_file(&missing),
+            Err(MarkovError::FileNotFound(missing))
+        );
+    }
+
+    #[test]
+    fn shrinking_frees_memory_after_removals() {
+        let mut chain = create_markov_chain_with_order(2).unwrap();
+        chain.train_text("the cat sat on the mat and the dog sat on the rug".to_string());
+        let removed: Vec<MarkovNodeID> = chain.nodes.iter().skip(2).map(|node| node.id).collect();
+        chain.remove_nodes(&removed);
+        let before = chain.memory_usage_bytes();
+        assert!(before > 0);
+        chain.shrink_to_fit();
+        assert!(chain.memory_usage_bytes() < before);
+        assert_eq!(chain.nodes.capacity(), chain.nodes.len());
+        assert!(chain.is_valid());
+    }
+
+    #[test]
+    fn progress_is_reported_and_can_cancel() {
+        let text = "one two three.\nfour five.\nsix seven eight.\nnine.\n";
+        let mut chain = MarkovChain::new();
+        let mut reports = Vec::new();
+        let stats = chain
+            .train_from_reader_with_progress(std::io::Cursor::new(text), 4, |progress| {
+                reports.push(progress);
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(stats.tokens, 9);
+        let tokens: Vec<usize> = reports.iter().map(|progress| progress.tokens).collect();
+        assert_eq!(tokens, [5, 9]);
+        assert_eq!(reports[0].bytes, 26);
+
+        let mut cancelled = MarkovChain::new();
+        let stats = cancelled
+            .train_from_reader_with_progress(std::io::Cursor::new(text), 1, |progress| {
+                if progress.lines == 2 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+            .unwrap();
+        assert_eq!(stats.tokens, 5);
+        assert!(cancelled.find_node("five").is_some());
+        assert!(cancelled.find_node("six").is_none());
+        assert!(cancelled.is_valid());
+    }
+
+    #[test]
+    fn forced_transitions_override_training() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("the end of it".to_string());
+        chain.add_forced_transition("End", "now", 3).unwrap();
+        let end = chain.find_node("end").unwrap();
+        assert_eq!(end.links.get(chain.find_node("now").unwrap().id), Some(3));
+        assert_eq!(chain.get_probability("end", "now"), 0.75);
+
+        chain.set_transition_weight("end", "now", 1).unwrap();
+        assert_eq!(chain.get_probability("end", "now"), 0.5);
+        chain.set_transition_weight("end", "of", 0).unwrap();
+        assert_eq!(chain.get_probability("end", "now"), 1.0);
+        assert!(chain.is_valid());
+
+        assert!(chain.add_forced_transition("end", "now", 0).is_err());
+        assert_eq!(
+            chain.set_transition_weight("end", "never", 2),
+            Err(MarkovError::WordNotFound("never".to_string()))
+        );
+    }
+
+    #[test]
+    fn weighted_training_counts_more() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a b".to_string());
+        chain.train_words_weighted(&["a", "c"], 3);
+        chain
+            .train_from_reader_weighted(std::io::Cursor::new("a b.\n"), 2)
+            .unwrap();
+        assert_eq!(chain.get_probability("a", "b"), 0.5);
+        assert_eq!(chain.get_probability("a", "c"), 0.5);
+        assert_eq!(chain.all_portions(), 18);
+        chain.train_words_weighted(&["x", "y"], 0);
+        chain
+            .train_from_reader_weighted(std::io::Cursor::new("x y"), 0)
+            .unwrap();
+        assert!(chain.find_node("x").is_none());
+        assert!(chain.is_valid());
+    }
+
+    #[test]
+    fn merging_parts_gives_the_whole() {
+        let mut whole = create_markov_chain_with_order(2).unwrap();
+        whole.train_text("the cat sat. the dog sat down".to_string());
+        whole.train_text("a cat ran".to_string());
+        let mut merged = whole.empty_like();
+        let mut part = whole.empty_like();
+        part.train_text("the cat sat. the dog sat down".to_string());
+        merged.merge(&part);
+        let mut part = whole.empty_like();
+        part.train_text("a cat ran".to_string());
+        merged.merge(&part);
+        assert_eq!(merged.to_json(), whole.to_json());
+        assert!(merged.is_valid());
+    }
+
+    #[cfg(all(feature = "rayon", not(feature = "wasm")))]
+    #[test]
+    fn parallel_files_train_like_serial_files() {
+        let dir = std::env::temp_dir().join("markov-chain-rs-train-files-parallel");
+        std::fs::create_dir_all(&dir).unwrap();
+        let texts = [
+            "the cat sat on the mat.",
+            "The dog sat.\nthe dog ran off",
+            "a bird sang. the cat ran",
+            "",
+            "the end",
+        ];
+        let paths: Vec<PathBuf> = texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                let path = dir.join(format!("{i}.txt"));
+                std::fs::write(&path, text).unwrap();
+                path
+            })
+            .collect();
+        let mut serial = MarkovChain::new();
+        for path in &paths {
+            serial.train_from_file(path).unwrap();
+        }
+        let mut parallel = MarkovChain::new();
+        let stats = parallel.train_files_parallel(&paths).unwrap();
+        assert_eq!(parallel.to_json(), serial.to_json());
+        assert_eq!(stats.tokens, 21);
+        assert_eq!(parallel.get_probability("the", "cat"), 2.0 / 6.0);
+
+        let missing = dir.join("missing.txt");
+        let mut with_missing = paths.clone();
+        with_missing.push(missing.clone());
+        assert_eq!(
+            parallel.train_files_parallel(&with_missing),
+            Err(MarkovError::FileNotFound(missing))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sentence_pairs_are_linked_by_position() {
+        let mut chain = MarkovChain::new();
+        chain.train_sentence_pairs([
+            ("the cat".to_string(), "le chat noir".to_string()),
+            ("hello".to_string(), String::new()),
+        ]);
+        let the = chain.find_node("the").unwrap();
+        assert!(the.links.has(chain.find_node("le").unwrap().id));
+        let cat = chain.find_node("cat").unwrap();
+        assert!(cat.links.has(chain.find_node("chat").unwrap().id));
+        assert!(!cat.links.has(chain.find_node("noir").unwrap().id));
+        assert_eq!(chain.find_node("hello").unwrap().links.len(), 1);
+        assert_eq!(chain.all_portions(), 11);
+        assert!(chain.is_valid());
+
+        #[derive(Debug, Default)]
+        struct CountingTokenizer(Arc<std::sync::atomic::AtomicUsize>);
+        impl Tokenizer for CountingTokenizer {
+            fn tokenize(&self, text: &str) -> Vec<String> {
+                self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                SimpleTokenizer::default().tokenize(text)
+            }
+        }
+        let calls = Arc::default();
+        let mut chain = MarkovChain::new();
+        chain.set_tokenizer(CountingTokenizer(Arc::clone(&calls)));
+        chain.train_sentence_pairs([("a b. c".to_string(), "d e".to_string())]);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn chains_can_be_shared_across_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<MarkovChain>();
+        assert_send_sync::<MarkovChain<u32>>();
+        assert_send_sync::<MarkovNode>();
+        assert_send_sync::<Map<MarkovNodeID, u64>>();
+        assert_send_sync::<TrainStats>();
+
+        let mut chain = MarkovChain::new();
+        chain.train_text("shared between threads".to_string());
+        let chain = Arc::new(chain);
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let chain = Arc::clone(&chain);
+                std::thread::spawn(move || chain.generate(10).unwrap())
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), ["shared", "between", "threads"]);
+        }
+    }
+
+    #[test]
+    fn chains_can_be_extended() {
+        let mut chain = MarkovChain::new();
+        chain.extend("one two\nthree four".lines().map(str::to_string));
+        assert_eq!(chain.root.links.len(), 2);
+        chain.extend([("two".to_string(), "three".to_string())]);
+        assert_eq!(chain.get_probability("two", "three"), 0.5);
+        assert_eq!(chain.all_portions(), 7);
+    }
+
+    #[test]
+    fn clones_are_independent() {
+        let mut chain = create_markov_chain_with_order(2).unwrap();
+        chain.train_text("the cat sat".to_string());
+        let mut clone = chain.clone();
+        clone.train_text("the cat ran".to_string());
+        clone
+            .add_forced_transition("cat sat", "sat down", 5)
+            .unwrap();
+        assert_eq!(chain.all_portions(), 3);
+        assert_eq!(chain.nodes.len(), 2);
+        assert_eq!(chain.find_node("the cat").unwrap().links.len(), 1);
+        assert_eq!(clone.find_node("the cat").unwrap().links.len(), 2);
+        assert_eq!(clone.all_portions(), 11);
+        assert!(chain.is_valid() && clone.is_valid());
+    }
+
+    #[test]
+    fn builder_options_change_training() {
+        let text = "The cat sat. The cat ran!".to_string();
+        let mut chain = MarkovChainBuilder::new().order(2).build().unwrap();
+        chain.train_text(text.clone());
+        assert_eq!(chain.order(), 2);
+        assert!(chain.find_node("the cat").is_some());
+
+        let mut chain = MarkovChainBuilder::new()
+            .keep_punctuation(true)
+            .build()
+            .unwrap();
+        chain.train_text(text.clone());
+        assert!(chain.find_node("!").is_some());
+
+        let mut chain = MarkovChainBuilder::new()
+            .tokenizer(SimpleTokenizer::aggressive())
+            .build()
+            .unwrap();
+        chain.train_text("don't stop".to_string());
+        assert!(chain.find_node("dont").is_some());
+
+        let mut chain = MarkovChainBuilder::new()
+            .stopwords(["THE".to_string()])
+            .build()
+            .unwrap();
+        chain.train_text(text);
+        assert!(chain.find_node("the").is_none());
+        assert_eq!(chain.root.links.len(), 1);
+
+        assert!(MarkovChainBuilder::new().order(0).build().is_err());
+        assert!(MarkovChainBuilder::new()
+            .keep_punctuation(true)
+            .tokenizer(SimpleTokenizer::default())
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn reset_portions_keep_the_structure() {
+        let mut chain = MarkovChain::new();
+        for _ in 0..3 {
+            chain.train_text("a b".to_string());
+        }
+        chain.train_text("a c".to_string());
+        assert_eq!(chain.get_probability("a", "b"), 0.75);
+        chain.reset_portions(1);
+        assert_eq!(chain.get_probability("a", "b"), 0.5);
+        assert_eq!(chain.all_portions(), 5);
+        assert!(chain.is_valid());
+        chain.train_text("a c".to_string());
+        assert_eq!(chain.get_probability("a", "c"), 2.0 / 3.0);
+    }
+
+    #[test]
+    fn bad_input_is_an_error_not_a_panic() {
+        let mut chain = MarkovChain::new();
+        chain.set_stopwords(["the".to_string()]);
+        chain.train_word("the", "cat");
+        chain.train_word("cat", "");
+        assert!(chain.nodes.is_empty());
+        let err = chain.set_cleanup_pattern("[").unwrap_err();
+        assert!(matches!(err, MarkovError::Parse(_)));
+        assert!(err.to_string().starts_with("failed to parse: "));
+        assert!(matches!(
+            create_markov_chain_with_order(0),
+            Err(MarkovError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn compacting_reclaims_removed_ids() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a b c d e".to_string());
+        let removed: Vec<MarkovNodeID> = ["a", "c"]
+            .iter()
+            .map(|word| chain.find_node(word).unwrap().id)
+            .collect();
+        chain.remove_nodes(&removed);
+        let before = chain.to_string();
+        chain.compact();
+        let ids: Vec<MarkovNodeID> = chain.nodes.iter().map(|node| node.id).collect();
+        assert_eq!(ids, [1, 2, 3]);
+        assert_eq!(chain.newest_id, 3);
+        assert_eq!(chain.to_string(), before);
+        assert_eq!(chain.get_probability("d", "e"), 1.0);
+        assert!(chain.is_valid());
+        chain.train_text("f".to_string());
+        assert_eq!(chain.find_node("f").unwrap().id, 4);
+    }
+
+    #[test]
+    fn swapping_exchanges_values() {
+        let mut map = Map::from_pairs(&[("a", 1), ("b", 2), ("c", 3)]);
+        map.swap("a", "c").unwrap();
+        assert_eq!(map.get("a"), Some(3));
+        assert_eq!(map.get("b"), Some(2));
+        assert_eq!(map.get("c"), Some(1));
+        assert_eq!(map.keys, ["a", "b", "c"]);
+        map.swap("b", "b").unwrap();
+        assert_eq!(map.get("b"), Some(2));
+        assert_eq!(map.swap("a", "d"), Err(MapError::KeyNotFound));
+        assert_eq!(map.get("a"), Some(3));
+    }
+
+    #[test]
+    fn sentences_are_trained_independently() {
+        let mut chain = MarkovChain::new();
+        chain.train_from_sentences(["Hello there", "general Kenobi", "...", "hi. there"]);
+        assert_eq!(chain.get_probability("there", "general"), 0.0);
+        assert_eq!(
+            chain.find_node("there").unwrap().links.get(chain.root.id),
+            Some(2)
+        );
+        assert_eq!(chain.get_probability("hi", "there"), 1.0);
+        let starts: Vec<&str> = chain
+            .root
+            .links
+            .iter()
+            .map(|(id, _)| chain.node(*id).unwrap().data.as_str())
+            .collect();
+        assert_eq!(starts, ["hello", "general", "hi"]);
+    }
+
+    #[test]
+    fn truncating_keeps_the_top_entries() {
+        let mut map = Map::from_pairs(&[
+            ("a", 4),
+            ("b", 9),
+            ("c", 1),
+            ("d", 7),
+            ("e", 3),
+            ("f", 8),
+            ("g", 5),
+        ]);
+        map.sort_by(|a, b| b.1.cmp(a.1));
+        map.truncate(5);
+        assert_eq!(map.clone().into_keys(), ["b", "f", "d", "g", "a"]);
+        assert!(!map.has("c") && !map.has("e"));
+        map.sort_by_value();
+        map.truncate(2);
+        assert_eq!(map.clone().into_vec(), [("a", 4), ("g", 5)]);
+        map.truncate(10);
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn tokens_trained_are_counted() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("the cat sat. the dog ran".to_string());
+        assert_eq!(chain.total_tokens_trained(), 6);
+        chain.train_text_weighted("a b", 3);
+        assert_eq!(chain.total_tokens_trained(), 12);
+        chain.train_word("x", "y");
+        chain.train_word("", "y");
+        chain.train_pairs(&[("p", "q", 2)]);
+        assert_eq!(chain.total_tokens_trained(), 18);
+        let mut other = MarkovChain::new();
+        other.train_text("one two three".to_string());
+        chain.merge(&other);
+        assert_eq!(chain.total_tokens_trained(), 21);
+        assert_eq!(chain.clone().total_tokens_trained(), 21);
+        assert!(chain
+            .to_json()
+            .starts_with(r#"{"order":1,"total_tokens_trained":21,"#));
+    }
+
+    #[test]
+    fn maps_can_find_positions_and_values() {
+        let map = Map::from_pairs(&[("a", 1), ("b", 2), ("c", 2)]);
+        assert_eq!(map.position("a"), Some(0));
+        assert_eq!(map.position("c"), Some(2));
+        assert_eq!(map.position("d"), None);
+        assert!(map.contains_value(&2));
+        assert!(!map.contains_value(&3));
+    }
+
+    #[test]
+    fn maps_shrink_to_their_length() {
+        let mut map: Map<u64, u64> = Map::with_capacity(100);
+        for i in 0..10 {
+            map.insert(i, i * 2);
+        }
+        let before = map.heap_bytes();
+        map.shrink_to_fit();
+        // shrinking only promises to get close to the length
+        let len_bytes = 10 * 2 * std::mem::size_of::<u64>();
+        assert!(map.heap_bytes() >= len_bytes);
+        assert!(map.heap_bytes() < before);
+        assert!(map.heap_bytes() <= before / 2);
+        assert_eq!(map.get(9), Some(18));
+    }
+
+    #[test]
+    fn maps_insert_in_batches() {
+        let mut map: Map<u64, u64> = Map::new();
+        map.insert(0, 0);
+        let items: Vec<(u64, u64)> = (1..1000).map(|i| (i, i * 2)).collect();
+        map.batch_insert(&items);
+        assert_eq!(map.len(), 1000);
+        assert!(map.heap_bytes() >= 1000 * 2 * std::mem::size_of::<u64>());
+        assert_eq!(map.get(999), Some(1998));
+        assert_eq!(map.position(500), Some(500));
+        map.batch_insert(&[]);
+        assert_eq!(map.len(), 1000);
     }
 }
@@ -1,12 +1,7 @@
-pub fn compare_const_strs(ptr1: *const str, ptr2: *const str) -> bool {
-    unsafe {
-        if ptr1.is_null() || ptr2.is_null() {
-            return false;
-        }
-
-        let str1: &str = &*ptr1;
-        let str2: &str = &*ptr2;
-
-        str1 == str2
-    }
+#[doc = "The compare_const_strs function compares two strings. Nodes own their"]
+#[doc = "words now, so there is nothing left to compare through raw pointers"]
+#[doc = "and this is the same as `str1 == str2`."]
+#[deprecated(note = "compare the strings with `==` instead")]
+pub fn compare_const_strs(str1: &str, str2: &str) -> bool {
+    str1 == str2
 }
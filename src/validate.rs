@@ -0,0 +1,166 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::error::{MarkovError, ValidationError};
+use crate::types::MarkovChain;
+
+impl MarkovChain {
+    #[doc = "The validate function checks the internals of the chain are"]
+    #[doc = "consistent with each other and returns every problem it found, so an"]
+    #[doc = "empty [Vec] means the chain is valid. This is mostly useful after"]
+    #[doc = "editing the public fields by hand."]
+    pub fn validate(&self) -> Vec<ValidationError> {
+        let mut out = Vec::new();
+        if self.nodes_map.len() != self.nodes.len() {
+            out.push(ValidationError::NodesMapLength {
+                nodes: self.nodes.len(),
+                nodes_map: self.nodes_map.len(),
+            });
+        }
+        for (id, idx) in self.nodes_map.iter() {
+            if self.nodes.get(*idx).map(|node| node.id) != Some(*id) {
+                out.push(ValidationError::NodesMapMismatch(*id));
+            }
+        }
+        let mut ids = HashSet::from([self.root.id]);
+        let mut words = HashSet::new();
+        for node in &self.nodes {
+            if !ids.insert(node.id) {
+                out.push(ValidationError::DuplicateId(node.id));
+            }
+            if !words.insert(node.data.as_str()) {
+                out.push(ValidationError::DuplicateWord(node.data.clone()));
+            }
+            if !self.nodes_map.contains_key(&node.id) {
+                out.push(ValidationError::MissingFromNodesMap(node.id));
+            }
+            if self.index.get(&node.data) != Some(&node.id) {
+                out.push(ValidationError::WordIndexMismatch(node.data.clone()));
+            }
+        }
+        let mut actual = 0;
+        let mut continuations: BTreeMap<usize, u64> = BTreeMap::new();
+        for node in std::iter::once(&self.root).chain(self.nodes.iter()) {
+            let mut seen = HashSet::new();
+            for (to, portions) in node.links.iter() {
+                let from = node.id;
+                let to = *to;
+                if !ids.contains(&to) {
+                    out.push(ValidationError::DanglingLink { from, to });
+                }
+                if *portions == 0 {
+                    out.push(ValidationError::ZeroPortionLink { from, to });
+                }
+                if seen.insert(to) {
+                    *continuations.entry(to).or_insert(0) += 1;
+                } else {
+                    out.push(ValidationError::DuplicateLink { from, to });
+                }
+                actual += portions;
+            }
+        }
+        if actual != self.all_portions {
+            out.push(ValidationError::AllPortionsMismatch {
+                stored: self.all_portions,
+                actual,
+            });
+        }
+        for id in self.continuation_counts.keys() {
+            continuations.entry(*id).or_insert(0);
+        }
+        for (id, actual) in continuations {
+            let stored = self.continuation_count(id);
+            if stored != actual {
+                out.push(ValidationError::ContinuationCountMismatch { id, stored, actual });
+            }
+        }
+        let max_id = ids.into_iter().max().unwrap_or(0);
+        if self.newest_id < max_id {
+            out.push(ValidationError::NewestIdTooLow {
+                newest_id: self.newest_id,
+                max_id,
+            });
+        }
+        out
+    }
+    #[doc = "The is_valid function returns if [MarkovChain::validate] found no"]
+    #[doc = "problems."]
+    pub fn is_valid(&self) -> bool {
+        self.validate().is_empty()
+    }
+    #[doc = "The check function returns the first problem"]
+    #[doc = "[MarkovChain::validate] found as [MarkovError::Corrupt], for using"]
+    #[doc = "with ? before trusting a chain that was edited by hand."]
+    pub fn check(&self) -> Result<(), MarkovError> {
+        match self.validate().into_iter().next() {
+            Some(err) => Err(MarkovError::Corrupt(err)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{MarkovError, ValidationError};
+    use crate::types::{Map, MarkovChain, MarkovNode};
+
+    #[test]
+    fn trained_chains_are_valid() {
+        let mut chain = MarkovChain::new();
+        assert!(chain.is_valid());
+        chain.train_text("the cat sat on the mat".to_string());
+        chain.train_word("dog", "cat");
+        assert_eq!(chain.validate(), []);
+        chain.remove_unreachable();
+        assert_eq!(chain.validate(), []);
+    }
+
+    #[test]
+    fn broken_chains_are_reported() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("the cat sat".to_string());
+        chain.nodes[0].links.insert(42, 0);
+        chain.nodes.push(MarkovNode {
+            data: "cat".to_string(),
+            id: 9,
+            links: Map::new(),
+        });
+        let errors = chain.validate();
+        assert!(errors.contains(&ValidationError::DanglingLink { from: 1, to: 42 }));
+        assert!(errors.contains(&ValidationError::ZeroPortionLink { from: 1, to: 42 }));
+        assert!(errors.contains(&ValidationError::DuplicateWord("cat".to_string())));
+        assert!(errors.contains(&ValidationError::MissingFromNodesMap(9)));
+        assert!(errors.contains(&ValidationError::NewestIdTooLow {
+            newest_id: 3,
+            max_id: 9
+        }));
+        // only "the" links to id 42, but nothing counted it
+        assert!(
+            errors.contains(&ValidationError::ContinuationCountMismatch {
+                id: 42,
+                stored: 0,
+                actual: 1
+            })
+        );
+        assert!(!chain.is_valid());
+        assert!(matches!(chain.check(), Err(MarkovError::Corrupt(_))));
+        assert_eq!(MarkovChain::new().check(), Ok(()));
+    }
+
+    #[test]
+    fn continuation_counts_are_checked() {
+        let mut chain = MarkovChain::new();
+        chain.train_text("a b. c b".to_string());
+        let b = chain.find_node("b").unwrap().id;
+        assert_eq!(chain.continuation_count(b), 2);
+        assert!(chain.is_valid());
+        chain.continuation_counts.insert(b, 5);
+        assert_eq!(
+            chain.validate(),
+            [ValidationError::ContinuationCountMismatch {
+                id: b,
+                stored: 5,
+                actual: 2
+            }]
+        );
+    }
+}
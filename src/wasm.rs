@@ -0,0 +1,39 @@
+use wasm_bindgen::prelude::*;
+
+use crate::types::{create_markov_chain_with_order, MarkovChain};
+
+#[doc = "The WasmMarkovChain struct wraps a [MarkovChain] for use from"]
+#[doc = "JavaScript through wasm-bindgen. Only available with the"]
+#[doc = "wasm-bindgen feature."]
+#[wasm_bindgen]
+pub struct WasmMarkovChain {
+    chain: MarkovChain,
+}
+
+#[wasm_bindgen]
+impl WasmMarkovChain {
+    #[doc = "The new function creates an empty chain of the provided order."]
+    #[wasm_bindgen(constructor)]
+    pub fn new(order: usize) -> Result<WasmMarkovChain, JsError> {
+        let chain =
+            create_markov_chain_with_order(order).map_err(|err| JsError::new(&err.to_string()))?;
+        Ok(WasmMarkovChain { chain })
+    }
+    #[wasm_bindgen(js_name = trainText)]
+    pub fn train_text(&mut self, text: &str) {
+        self.chain.train_text(text.to_string());
+    }
+    #[doc = "The generate function generates text of up to max_words words, see"]
+    #[doc = "[MarkovChain::generate_text]."]
+    pub fn generate(&self, max_words: usize) -> Result<String, JsError> {
+        self.chain
+            .generate_text(max_words)
+            .map_err(|err| JsError::new(&err.to_string()))
+    }
+    #[doc = "The to_json function returns the chain as JSON, see"]
+    #[doc = "[MarkovChain::to_json]."]
+    #[wasm_bindgen(js_name = toJson)]
+    pub fn to_json(&self) -> String {
+        self.chain.to_json()
+    }
+}